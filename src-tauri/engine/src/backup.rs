@@ -0,0 +1,783 @@
+use crate::error::Error;
+use crate::rewrite::RefUpdate;
+use crate::worktree::prepare_worktree;
+use git2::{Oid, Repository, Signature};
+use serde::{Deserialize, Serialize};
+
+const BACKUP_REF_PREFIX: &str = "refs/git-history-editor/backups/";
+const RECOVERY_REF_PREFIX: &str = "refs/git-history-editor/recovery/";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupKind {
+    /// Recorded before a rewrite moved the refs.
+    Rewrite,
+    /// Recorded when a rewrite (or redo) entry was restored.
+    Undo,
+    /// Recorded when an undo entry was restored.
+    Redo,
+}
+
+/// One ref saved in a backup entry: `backup_oid` is where the ref pointed
+/// before the operation, `rewritten_oid` where the operation moved it. A zero
+/// OID on either side means the ref did not exist (it was created or deleted).
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupRef {
+    pub ref_name: String,
+    pub backup_oid: String,
+    pub rewritten_oid: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BackupEntry {
+    pub id: String,
+    pub timestamp: i64,
+    pub kind: BackupKind,
+    pub description: String,
+    pub refs: Vec<BackupRef>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct BackupInfo {
+    pub exists: bool,
+    pub backup_oid: Option<String>,
+    pub branch: String,
+    /// The entry `restore_backup` restores when called without an id.
+    pub entry_id: Option<String>,
+    pub description: Option<String>,
+    pub can_redo: bool,
+}
+
+/// Metadata stored as JSON in the message of each backup tag object.
+#[derive(Serialize, Deserialize)]
+struct BackupMeta {
+    kind: BackupKind,
+    description: String,
+    rewritten_oid: String,
+    /// The ref did not exist before; the tag then points at its new target.
+    #[serde(default)]
+    created: bool,
+}
+
+fn backup_tagger() -> Result<Signature<'static>, Error> {
+    Ok(Signature::now(
+        "git-history-editor",
+        "git-history-editor@localhost",
+    )?)
+}
+
+/// Move each ref in `moves` from `.1` to `.2` and record a backup entry for
+/// the move. A zero `.1` creates the ref and a zero `.2` deletes it. Each ref
+/// is saved as `refs/git-history-editor/backups/<id>/<ref without refs/>`,
+/// pointing at an annotated tag that keeps the old tip reachable (the new one
+/// for a created ref) and carries the metadata.
+///
+/// All ref changes, backup refs included, go through one `git2::Transaction`:
+/// every ref is locked up front, then checked against `expected` (the OID it
+/// must still point at, `None` if it must not exist). If one was moved by
+/// someone else, nothing is written and its name is returned.
+pub(crate) fn move_refs_with_backup(
+    repo: &Repository,
+    kind: BackupKind,
+    description: &str,
+    moves: &[(String, Oid, Oid)],
+    expected: &[Option<Oid>],
+) -> Result<Option<String>, Error> {
+    let tagger = backup_tagger()?;
+    let mut millis = chrono::Utc::now().timestamp_millis();
+    let id = loop {
+        let candidate = format!("{:013}", millis);
+        let prefix = format!("{}{}/", BACKUP_REF_PREFIX, candidate);
+        let taken = repo
+            .references_glob(&format!("{}*", prefix))?
+            .next()
+            .is_some();
+        if !taken {
+            break candidate;
+        }
+        millis += 1;
+    };
+
+    // Tag objects are written first; if the transaction is abandoned they are
+    // unreachable and git garbage-collects them
+    let mut backup_refs = Vec::new();
+    for (ref_name, old_oid, new_oid) in moves {
+        let meta = BackupMeta {
+            kind,
+            description: description.to_string(),
+            rewritten_oid: new_oid.to_string(),
+            created: old_oid.is_zero(),
+        };
+        let message = serde_json::to_string(&meta).map_err(|e| Error::Internal(e.to_string()))?;
+        let target = repo.find_object(if meta.created { *new_oid } else { *old_oid }, None)?;
+        let tag_oid = repo.tag_annotation_create(&id, &target, &tagger, &message)?;
+        let backup_name = format!(
+            "{}{}/{}",
+            BACKUP_REF_PREFIX,
+            id,
+            ref_name.strip_prefix("refs/").unwrap_or(ref_name)
+        );
+        backup_refs.push((backup_name, tag_oid));
+    }
+
+    let mut transaction = repo.transaction()?;
+    for ref_name in moves
+        .iter()
+        .map(|(name, _, _)| name)
+        .chain(backup_refs.iter().map(|(name, _)| name))
+    {
+        transaction
+            .lock_ref(ref_name)
+            .map_err(|e| Error::ref_update(repo, ref_name, e))?;
+    }
+    // With the locks held nobody else can move the refs before the commit
+    for ((ref_name, _, _), expected) in moves.iter().zip(expected) {
+        if repo.refname_to_id(ref_name).ok() != *expected {
+            return Ok(Some(ref_name.clone()));
+        }
+    }
+
+    for (backup_name, tag_oid) in &backup_refs {
+        transaction.set_target(backup_name, *tag_oid, None, "git-history-editor: backup")?;
+    }
+    let log_message = format!("git-history-editor: {}", description);
+    for (ref_name, _, new_oid) in moves {
+        if new_oid.is_zero() {
+            transaction.remove(ref_name)?;
+        } else {
+            transaction.set_target(ref_name, *new_oid, None, &log_message)?;
+        }
+    }
+    transaction.commit()?;
+    Ok(None)
+}
+
+/// Point `refs/git-history-editor/recovery/<id>/<ref without refs/>` at the
+/// new tip of each move, so a rewrite whose refs could not be moved is not
+/// lost to garbage collection. Returns the recovery ref names.
+pub(crate) fn keep_for_recovery(
+    repo: &Repository,
+    moves: &[(String, Oid, Oid)],
+) -> Result<Vec<String>, Error> {
+    let id = format!("{:013}", chrono::Utc::now().timestamp_millis());
+    let mut recovery_refs = Vec::new();
+    for (ref_name, _, new_oid) in moves {
+        let name = format!(
+            "{}{}/{}",
+            RECOVERY_REF_PREFIX,
+            id,
+            ref_name.strip_prefix("refs/").unwrap_or(ref_name)
+        );
+        repo.reference(&name, *new_oid, true, "git-history-editor: recovery")
+            .map_err(|e| Error::ref_update(repo, &name, e))?;
+        recovery_refs.push(name);
+    }
+    Ok(recovery_refs)
+}
+
+/// All backup entries, newest first.
+pub fn list_backup_entries(repo: &Repository) -> Result<Vec<BackupEntry>, Error> {
+    let mut entries: Vec<BackupEntry> = Vec::new();
+
+    for reference in repo.references_glob(&format!("{}*", BACKUP_REF_PREFIX))? {
+        let reference = reference?;
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        let Some((id, rest)) = name
+            .strip_prefix(BACKUP_REF_PREFIX)
+            .and_then(|r| r.split_once('/'))
+        else {
+            continue;
+        };
+        let Some(tag) = reference.target().and_then(|oid| repo.find_tag(oid).ok()) else {
+            continue;
+        };
+        let Some(meta) = tag
+            .message()
+            .and_then(|m| serde_json::from_str::<BackupMeta>(m).ok())
+        else {
+            continue;
+        };
+
+        let backup_oid = if meta.created {
+            Oid::zero()
+        } else {
+            tag.target_id()
+        };
+        let backup_ref = BackupRef {
+            ref_name: format!("refs/{}", rest),
+            backup_oid: backup_oid.to_string(),
+            rewritten_oid: meta.rewritten_oid,
+        };
+        match entries.iter_mut().find(|e| e.id == id) {
+            Some(entry) => entry.refs.push(backup_ref),
+            None => entries.push(BackupEntry {
+                id: id.to_string(),
+                timestamp: tag.tagger().map(|t| t.when().seconds()).unwrap_or(0),
+                kind: meta.kind,
+                description: meta.description,
+                refs: vec![backup_ref],
+            }),
+        }
+    }
+
+    entries.sort_by(|a, b| b.id.cmp(&a.id));
+    Ok(entries)
+}
+
+/// Newest entry of one of `kinds` that covers `ref_name` and whose operation
+/// is still in effect, i.e. the ref still points where that entry moved it.
+fn current_backup_entry(
+    repo: &Repository,
+    ref_name: &str,
+    kinds: &[BackupKind],
+) -> Result<Option<BackupEntry>, Error> {
+    let tip = repo
+        .find_reference(ref_name)
+        .ok()
+        .and_then(|r| r.target())
+        .map(|o| o.to_string());
+    let Some(tip) = tip else { return Ok(None) };
+
+    Ok(list_backup_entries(repo)?.into_iter().find(|entry| {
+        kinds.contains(&entry.kind)
+            && entry
+                .refs
+                .iter()
+                .any(|r| r.ref_name == ref_name && r.rewritten_oid == tip)
+    }))
+}
+
+/// Move every ref in a backup entry back to its saved OID, recreating refs
+/// deleted since and deleting refs the entry's operation created. The restore
+/// is itself recorded as an entry, so it can be redone (or undone) later.
+pub fn restore_backup_entry(repo: &Repository, entry_id: &str) -> Result<Vec<RefUpdate>, Error> {
+    let entry = list_backup_entries(repo)?
+        .into_iter()
+        .find(|e| e.id == entry_id)
+        .ok_or_else(|| Error::BackupNotFound {
+            entry_id: Some(entry_id.to_string()),
+        })?;
+
+    let mut moves = Vec::new();
+    // Where each ref pointed when the entry was read; `None` if it does not exist
+    let mut expected = Vec::new();
+    for backup_ref in &entry.refs {
+        let target = Oid::from_str(&backup_ref.backup_oid)?;
+        let current = repo
+            .find_reference(&backup_ref.ref_name)
+            .ok()
+            .and_then(|r| r.target())
+            .unwrap_or_else(Oid::zero);
+        if current != target {
+            moves.push((backup_ref.ref_name.clone(), current, target));
+            expected.push((!current.is_zero()).then_some(current));
+        }
+    }
+
+    let kind = match entry.kind {
+        BackupKind::Undo => BackupKind::Redo,
+        _ => BackupKind::Undo,
+    };
+    let verb = if kind == BackupKind::Redo {
+        "redid"
+    } else {
+        "undid"
+    };
+    let description = format!("{} \"{}\"", verb, entry.description);
+    if !moves.is_empty() {
+        let sync = prepare_worktree(repo, &moves, false)?;
+        let moved = match move_refs_with_backup(repo, kind, &description, &moves, &expected) {
+            Ok(moved) => moved,
+            Err(e) => {
+                if let Some(sync) = sync {
+                    sync.roll_back()?;
+                }
+                return Err(e);
+            }
+        };
+        if let Some(ref_name) = moved {
+            if let Some(sync) = sync {
+                sync.roll_back()?;
+            }
+            // The restored commits are kept by the entry itself, so nothing needs recovering
+            return Err(Error::RefMoved {
+                ref_name,
+                recovery_refs: Vec::new(),
+            });
+        }
+        if let Some(sync) = sync {
+            sync.finish()?;
+        }
+    }
+
+    let mut updated = Vec::new();
+    for (ref_name, old_oid, new_oid) in moves {
+        updated.push(RefUpdate {
+            ref_name,
+            old_oid: old_oid.to_string(),
+            new_oid: new_oid.to_string(),
+        });
+    }
+    Ok(updated)
+}
+
+fn head_branch_ref(repo: &Repository, action: &str) -> Result<String, Error> {
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(Error::DetachedHead {
+            action: action.to_string(),
+        });
+    }
+    Ok(head
+        .name()
+        .ok_or_else(|| Error::Internal("HEAD ref name is not valid UTF-8".to_string()))?
+        .to_string())
+}
+
+/// Restore `entry_id`, or the most recent rewrite of HEAD's branch that is
+/// still in effect. Returns the OID HEAD's branch (or the first ref) now points to.
+pub fn undo_rewrite(repo: &Repository, entry_id: Option<&str>) -> Result<String, Error> {
+    let branch_ref_name = head_branch_ref(repo, "restore")?;
+    let entry_id = match entry_id {
+        Some(id) => id.to_string(),
+        None => {
+            current_backup_entry(
+                repo,
+                &branch_ref_name,
+                &[BackupKind::Rewrite, BackupKind::Redo],
+            )?
+            .ok_or(Error::BackupNotFound { entry_id: None })?
+            .id
+        }
+    };
+    let updated = restore_backup_entry(repo, &entry_id)?;
+    restored_tip(repo, &branch_ref_name, &updated)
+}
+
+/// Redo the most recent undo of HEAD's branch, if nothing moved it since.
+pub fn redo_rewrite(repo: &Repository) -> Result<String, Error> {
+    let branch_ref_name = head_branch_ref(repo, "redo")?;
+    let entry = current_backup_entry(repo, &branch_ref_name, &[BackupKind::Undo])?
+        .ok_or(Error::NothingToRedo)?;
+    let updated = restore_backup_entry(repo, &entry.id)?;
+    restored_tip(repo, &branch_ref_name, &updated)
+}
+
+fn restored_tip(
+    repo: &Repository,
+    branch_ref_name: &str,
+    updated: &[RefUpdate],
+) -> Result<String, Error> {
+    match updated
+        .iter()
+        .find(|u| u.ref_name == branch_ref_name)
+        .or(updated.first())
+    {
+        Some(update) => Ok(update.new_oid.clone()),
+        None => Ok(repo.refname_to_id(branch_ref_name)?.to_string()),
+    }
+}
+
+/// Whether HEAD's branch can currently be undone or redone.
+pub fn backup_status(repo: &Repository) -> Result<BackupInfo, Error> {
+    let head = repo.head().ok();
+    let branch = head
+        .as_ref()
+        .and_then(|h| h.shorthand().map(String::from))
+        .unwrap_or_else(|| "unknown".to_string());
+    let branch_ref_name = head
+        .as_ref()
+        .filter(|h| h.is_branch())
+        .and_then(|h| h.name().map(String::from));
+
+    let Some(branch_ref_name) = branch_ref_name else {
+        return Ok(BackupInfo {
+            exists: false,
+            backup_oid: None,
+            branch,
+            entry_id: None,
+            description: None,
+            can_redo: false,
+        });
+    };
+
+    let undo = current_backup_entry(
+        repo,
+        &branch_ref_name,
+        &[BackupKind::Rewrite, BackupKind::Redo],
+    )?;
+    let can_redo = current_backup_entry(repo, &branch_ref_name, &[BackupKind::Undo])?.is_some();
+
+    let result = match undo {
+        Some(entry) => BackupInfo {
+            exists: true,
+            backup_oid: entry
+                .refs
+                .iter()
+                .find(|r| r.ref_name == branch_ref_name)
+                .map(|r| r.backup_oid.clone()),
+            branch,
+            entry_id: Some(entry.id),
+            description: Some(entry.description),
+            can_redo,
+        },
+        None => BackupInfo {
+            exists: false,
+            backup_oid: None,
+            branch,
+            entry_id: None,
+            description: None,
+            can_redo,
+        },
+    };
+    Ok(result)
+}
+
+/// Which backup entries `prune_backups` deletes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupPrune {
+    /// Entries recorded before this Unix timestamp (seconds).
+    OlderThan(i64),
+    /// Every entry but the newest `n`.
+    KeepLast(usize),
+}
+
+/// Delete the refs of the given entries in one ref transaction.
+fn delete_entries(repo: &Repository, ids: &[String]) -> Result<(), Error> {
+    let mut ref_names = Vec::new();
+    for id in ids {
+        for reference in repo.references_glob(&format!("{}{}/*", BACKUP_REF_PREFIX, id))? {
+            if let Some(name) = reference?.name() {
+                ref_names.push(name.to_string());
+            }
+        }
+    }
+    let mut transaction = repo.transaction()?;
+    for ref_name in &ref_names {
+        transaction
+            .lock_ref(ref_name)
+            .map_err(|e| Error::ref_update(repo, ref_name, e))?;
+        transaction.remove(ref_name)?;
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+/// Delete one backup entry. The commits and objects only it kept reachable
+/// can then be garbage-collected by git, and the entry can no longer be restored.
+pub fn delete_backup_entry(repo: &Repository, entry_id: &str) -> Result<(), Error> {
+    if !list_backup_entries(repo)?.iter().any(|e| e.id == entry_id) {
+        return Err(Error::BackupNotFound {
+            entry_id: Some(entry_id.to_string()),
+        });
+    }
+    delete_entries(repo, &[entry_id.to_string()])
+}
+
+/// Delete the backup entries selected by `prune`. Returns the deleted entry IDs.
+pub fn prune_backups(repo: &Repository, prune: &BackupPrune) -> Result<Vec<String>, Error> {
+    let entries = list_backup_entries(repo)?;
+    let ids: Vec<String> = match prune {
+        BackupPrune::OlderThan(timestamp) => entries
+            .into_iter()
+            .filter(|e| e.timestamp < *timestamp)
+            .map(|e| e.id)
+            .collect(),
+        BackupPrune::KeepLast(n) => entries.into_iter().skip(*n).map(|e| e.id).collect(),
+    };
+    delete_entries(repo, &ids)?;
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::list_commits;
+    use crate::rewrite::{rewrite_commit, CommitEdit, RewriteOptions};
+    use crate::test_support::{commit_files, create_test_repo};
+
+    #[test]
+    fn test_backup_and_restore() {
+        let (_dir, repo) = create_test_repo(2);
+
+        let commits_before = list_commits(&repo, 0, 10).unwrap();
+        let original_head_oid = commits_before[0].oid.clone();
+
+        // Rewrite and verify backup exists
+        let target_oid = Oid::from_str(&original_head_oid).unwrap();
+        rewrite_commit(
+            &repo,
+            target_oid,
+            CommitEdit {
+                message: Some("Rewritten".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        let backup = backup_status(&repo).unwrap();
+        assert!(backup.exists);
+        assert_eq!(
+            backup.backup_oid.as_deref(),
+            Some(original_head_oid.as_str())
+        );
+
+        // Restore
+        let restored_oid = undo_rewrite(&repo, None).unwrap();
+        assert_eq!(restored_oid, original_head_oid);
+
+        // Nothing left to undo, but the restore can be redone
+        let backup_after = backup_status(&repo).unwrap();
+        assert!(!backup_after.exists);
+        assert!(backup_after.can_redo);
+
+        // Commits should be back to original
+        let commits_after = list_commits(&repo, 0, 10).unwrap();
+        assert_eq!(commits_after[0].oid, original_head_oid);
+    }
+
+    #[test]
+    fn test_multi_level_undo_and_redo() {
+        let (_dir, repo) = create_test_repo(2);
+        let original = repo.head().unwrap().target().unwrap().to_string();
+
+        let first = rewrite_commit(
+            &repo,
+            Oid::from_str(&original).unwrap(),
+            CommitEdit {
+                message: Some("First edit".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        let second = rewrite_commit(
+            &repo,
+            Oid::from_str(&first.new_oid).unwrap(),
+            CommitEdit {
+                message: Some("Second edit".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        // Both rewrites are kept, newest first
+        let entries = list_backup_entries(&repo).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, BackupKind::Rewrite);
+        assert_eq!(entries[0].refs[0].backup_oid, first.new_oid);
+        assert_eq!(entries[0].refs[0].rewritten_oid, second.new_oid);
+        assert_eq!(entries[1].refs[0].backup_oid, original);
+        assert!(entries[1].description.contains(&original[..8]));
+
+        // Undo twice walks back past the first rewrite
+        assert_eq!(undo_rewrite(&repo, None).unwrap(), first.new_oid);
+        assert_eq!(undo_rewrite(&repo, None).unwrap(), original);
+        assert!(!backup_status(&repo).unwrap().exists);
+
+        // Redo brings the first rewrite back, and it can be undone again
+        assert_eq!(redo_rewrite(&repo).unwrap(), first.new_oid);
+        let info = backup_status(&repo).unwrap();
+        assert!(info.exists);
+        assert_eq!(info.backup_oid.as_deref(), Some(original.as_str()));
+
+        // Any entry can be restored directly
+        let oldest = list_backup_entries(&repo).unwrap().pop().unwrap();
+        undo_rewrite(&repo, Some(&oldest.id)).unwrap();
+        assert_eq!(repo.head().unwrap().target().unwrap().to_string(), original);
+
+        // All rewritten commits stay reachable from the backup refs
+        assert!(repo
+            .find_commit(Oid::from_str(&second.new_oid).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_ref_moves_and_backup_are_one_transaction() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().target().unwrap();
+        let parent = repo.find_commit(head).unwrap().parent_id(0).unwrap();
+        repo.reference("refs/heads/side", parent, false, "test")
+            .unwrap();
+        let moves = vec![
+            ("refs/heads/main".to_string(), head, parent),
+            ("refs/heads/side".to_string(), parent, head),
+        ];
+
+        // One stale expectation and nothing is written, not even the backup
+        let stale = [Some(head), Some(head)];
+        let moved =
+            move_refs_with_backup(&repo, BackupKind::Rewrite, "swap", &moves, &stale).unwrap();
+        assert_eq!(moved.as_deref(), Some("refs/heads/side"));
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), head);
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+
+        let current = [Some(head), Some(parent)];
+        let moved =
+            move_refs_with_backup(&repo, BackupKind::Rewrite, "swap", &moves, &current).unwrap();
+        assert_eq!(moved, None);
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), parent);
+        assert_eq!(repo.refname_to_id("refs/heads/side").unwrap(), head);
+        let entries = list_backup_entries(&repo).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].refs.len(), 2);
+
+        // A ref that must not exist yet is created
+        let create = vec![("refs/heads/new".to_string(), Oid::zero(), head)];
+        assert_eq!(
+            move_refs_with_backup(&repo, BackupKind::Undo, "create", &create, &[None]).unwrap(),
+            None
+        );
+        assert_eq!(repo.refname_to_id("refs/heads/new").unwrap(), head);
+    }
+
+    #[test]
+    fn test_undo_and_redo_recreate_and_delete_a_deleted_branch() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().target().unwrap();
+        repo.reference("refs/heads/side", head, false, "test")
+            .unwrap();
+        let options = RewriteOptions {
+            refs: crate::rewrite::RefSelection::LocalBranches,
+            ..Default::default()
+        };
+        let edit = CommitEdit {
+            message: Some("Rewritten".to_string()),
+            ..Default::default()
+        };
+        let rewritten = rewrite_commit(&repo, head, edit, &options, &|_, _| {}).unwrap();
+        repo.find_reference("refs/heads/side")
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        // Undo recreates the deleted branch and reports it as created
+        let rewrite_entry = list_backup_entries(&repo).unwrap().remove(0);
+        let updated = restore_backup_entry(&repo, &rewrite_entry.id).unwrap();
+        let side = updated
+            .iter()
+            .find(|u| u.ref_name == "refs/heads/side")
+            .unwrap();
+        assert_eq!(side.old_oid, Oid::zero().to_string());
+        assert_eq!(side.new_oid, head.to_string());
+        assert_eq!(repo.refname_to_id("refs/heads/side").unwrap(), head);
+        let undo_entry = list_backup_entries(&repo).unwrap().remove(0);
+        let side_backup = undo_entry
+            .refs
+            .iter()
+            .find(|r| r.ref_name == "refs/heads/side")
+            .unwrap();
+        assert_eq!(side_backup.backup_oid, Oid::zero().to_string());
+
+        // Redo deletes it again
+        assert_eq!(redo_rewrite(&repo).unwrap(), rewritten.new_oid);
+        assert!(repo.find_reference("refs/heads/side").is_err());
+    }
+
+    /// Whether any commit reachable from any ref has `blob` in its tree.
+    fn blob_reachable(repo: &Repository, blob: Oid) -> bool {
+        let mut revwalk = repo.revwalk().unwrap();
+        for reference in repo.references().unwrap() {
+            let reference = reference.unwrap();
+            if let Ok(commit) = reference.peel_to_commit() {
+                revwalk.push(commit.id()).unwrap();
+            }
+        }
+        revwalk
+            .map(|oid| repo.find_commit(oid.unwrap()).unwrap())
+            .any(|commit| {
+                let mut found = false;
+                commit
+                    .tree()
+                    .unwrap()
+                    .walk(git2::TreeWalkMode::PreOrder, |_, entry| {
+                        found |= entry.id() == blob;
+                        git2::TreeWalkResult::Ok
+                    })
+                    .unwrap();
+                found
+            })
+    }
+
+    #[test]
+    fn test_pruning_makes_purged_blobs_unreachable() {
+        let (_dir, repo) = create_test_repo(1);
+        commit_files(&repo, &[(".env", Some(b"TOKEN=secret\n"))], "Add env");
+        commit_files(&repo, &[("other.txt", Some(b"other\n"))], "Add other");
+        let secret = repo.blob(b"TOKEN=secret\n").unwrap();
+
+        crate::purge::remove_paths(
+            &repo,
+            &crate::purge::RemovePathsOptions {
+                paths: vec![".env".to_string()],
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        // The backup entry still keeps the purged blob reachable
+        assert!(blob_reachable(&repo, secret));
+        let entries = list_backup_entries(&repo).unwrap();
+        assert_eq!(entries.len(), 1);
+
+        // Keeping the newest entry deletes nothing
+        assert!(prune_backups(&repo, &BackupPrune::KeepLast(1))
+            .unwrap()
+            .is_empty());
+        assert!(prune_backups(&repo, &BackupPrune::OlderThan(0))
+            .unwrap()
+            .is_empty());
+
+        let pruned = prune_backups(&repo, &BackupPrune::KeepLast(0)).unwrap();
+        assert_eq!(pruned, vec![entries[0].id.clone()]);
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+        assert!(!blob_reachable(&repo, secret));
+    }
+
+    #[test]
+    fn test_delete_backup_entry() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().target().unwrap();
+        for message in ["First", "Second"] {
+            let tip = repo.head().unwrap().target().unwrap();
+            let edit = CommitEdit {
+                message: Some(message.to_string()),
+                ..Default::default()
+            };
+            rewrite_commit(&repo, tip, edit, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        }
+        assert_ne!(repo.head().unwrap().target().unwrap(), head);
+        let entries = list_backup_entries(&repo).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        delete_backup_entry(&repo, &entries[1].id).unwrap();
+        let remaining = list_backup_entries(&repo).unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, entries[0].id);
+
+        let err = delete_backup_entry(&repo, &entries[1].id).unwrap_err();
+        assert_eq!(
+            err,
+            Error::BackupNotFound {
+                entry_id: Some(entries[1].id.clone())
+            }
+        );
+    }
+
+    #[test]
+    fn test_no_backup_initially() {
+        let (_dir, repo) = create_test_repo(1);
+        let backup = backup_status(&repo).unwrap();
+        assert!(!backup.exists);
+        assert!(backup.backup_oid.is_none());
+    }
+}
@@ -0,0 +1,702 @@
+//! `ghe` — headless command-line front end for the history editor.
+//!
+//! Runs the same engine as the desktop app without opening a window, prints
+//! JSON on stdout and reports failures as `{"error": {"kind": ..., "message": ...}}`
+//! on stderr. Exit codes: 0 success, 1 the git operation failed, 2 invalid usage.
+
+use git_history_engine::{
+    self as engine, BackupPrune, CommitEdit, CommitFilters, CommitSelection, DateRewrite,
+    IdentityRole, MessageRule, RefSelection, RemovePathsOptions, RewriteOptions, ScrubOptions,
+    SigningMode, SplitGroup, SquashMode, SquashOptions, TagRewrite, TrailerEdit,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: ghe <command> [options]
+
+Commands:
+  open                      Show branch and commit count
+  log                       List commits (newest first)
+      --offset N --limit N
+      --author-name S --author-email S --message S
+      --since UNIX_TS --until UNIX_TS --path GLOB
+  show <oid>                Show full commit metadata
+  published <oid>           List remote-tracking refs and tags that already contain a commit
+  files <oid>               List changed files with line stats, per parent
+  diff <oid> <path>         Show the unified patch of one file
+      --parent N --max-bytes N
+  edit <oid>                Rewrite one commit and its descendants
+      --author-name S --author-email S --author-date UNIX_TS --author-offset MIN
+      --committer-name S --committer-email S --committer-date UNIX_TS --committer-offset MIN
+      --message S | --message-file FILE
+      --add-trailer 'KEY: VALUE' --set-trailer 'KEY: VALUE' --remove-trailer KEY
+      --refs head|local-branches|all|REF[,REF...]
+      --sign                 re-sign signed commits with the git signing config (gpg.format, user.signingkey)
+      --dry-run
+      --allow-published      rewrite commits a remote-tracking ref or tag already contains
+      --auto-stash           stash local changes the new HEAD would overwrite, re-apply them after
+      --tags keep|update|rename:SUFFIX
+                             move or recreate tags on rewritten commits, or copy them as NAME+SUFFIX
+  trailers                  Add, replace or remove a trailer on every selected commit
+      --add-trailer 'KEY: VALUE' --set-trailer 'KEY: VALUE' --remove-trailer KEY
+      --range A..B           only commits in this revision range
+      --author-name S ... --path GLOB (filters as for log)
+      --refs ... --sign ... --dry-run (as for edit)
+  dates                     Shift or normalize the dates of every selected commit
+      --shift SECONDS        add to each date (negative moves back)
+      --offset MIN           convert to this UTC offset, keeping the instant
+      --only author|committer
+      --committer-date-is-author-date --require-monotonic
+      --range A..B --author-name S ... --path GLOB (as for trailers)
+      --refs ... --sign ... --dry-run (as for edit)
+  messages                  Rewrite commit messages with regex substitutions
+      --pattern REGEX --replacement S   one rule; $1 or ${name} refer to capture groups
+      --rules JSON           or an array of {\"pattern\": regex, \"replacement\": text} objects
+      --preview              print before/after messages instead of rewriting
+      --range A..B --author-name S ... --path GLOB (as for trailers)
+      --refs ... --sign ... --dry-run (as for edit)
+  squash <oid> <oid>...     Squash consecutive commits into one
+      --fixup --template S --message S --author-oid OID
+      --refs ... --sign ... --dry-run (as for edit)
+  reorder <oid> <oid>...    Reorder consecutive commits; list them oldest first, in the new order
+      --refs ... --sign ... --dry-run (as for edit)
+  split <oid>               Split a commit into one commit per path group
+      --groups JSON          array of {\"paths\": [pathspec...], \"message\": text} objects
+      --refs ... --sign ... --dry-run (as for edit)
+  drop <oid>...             Drop commits, replaying their descendants without them
+      --refs ... --sign ... --dry-run (as for edit)
+  remove-paths <path>...    Remove paths (git pathspecs) from every commit
+      --prune-empty          drop commits left without changes
+      --refs ... --sign ... --dry-run (as for edit)
+  scrub <text>...           Replace text in every file of history with ***REMOVED***
+      --regex                treat the texts as regular expressions
+      --replacement S --include-binary
+      --refs ... --sign ... --dry-run (as for edit)
+  backup                    Show whether the current branch can be undone/redone
+  backups                   List all backup entries
+  restore [entry-id]        Undo the last rewrite, or restore a specific entry
+  redo                      Redo the last undo
+  delete-backup <entry-id>  Delete one backup entry
+  prune-backups             Delete old backup entries so git gc can drop what only they kept
+      --older-than UNIX_TS | --keep-last N
+
+Global options:
+  --repo PATH               Repository to operate on (default: current directory)
+  -h, --help                Show this help
+";
+
+enum CliError {
+    Usage(String),
+    Failed(engine::Error),
+}
+
+impl From<engine::Error> for CliError {
+    fn from(error: engine::Error) -> Self {
+        CliError::Failed(error)
+    }
+}
+
+/// Parsed command line: positional arguments plus `--flag value` options.
+/// Boolean flags are stored with an empty value.
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+const BOOLEAN_FLAGS: &[&str] = &[
+    "allow-published",
+    "auto-stash",
+    "committer-date-is-author-date",
+    "dry-run",
+    "fixup",
+    "help",
+    "include-binary",
+    "preview",
+    "prune-empty",
+    "regex",
+    "require-monotonic",
+    "sign",
+];
+
+impl Args {
+    fn parse(mut raw: impl Iterator<Item = String>) -> Result<Args, CliError> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+
+        while let Some(arg) = raw.next() {
+            let Some(flag) =
+                arg.strip_prefix("--")
+                    .or(if arg == "-h" { Some("help") } else { None })
+            else {
+                positional.push(arg);
+                continue;
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None if BOOLEAN_FLAGS.contains(&flag) => (flag.to_string(), String::new()),
+                None => {
+                    let value = raw
+                        .next()
+                        .ok_or_else(|| CliError::Usage(format!("--{} needs a value", flag)))?;
+                    (flag.to_string(), value)
+                }
+            };
+            options.insert(name, value);
+        }
+
+        Ok(Args {
+            positional,
+            options,
+        })
+    }
+
+    fn take(&mut self, name: &str) -> Option<String> {
+        self.options.remove(name)
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        self.options.remove(name).is_some()
+    }
+
+    fn take_parsed<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>, CliError> {
+        self.take(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| CliError::Usage(format!("Invalid value for --{}: {}", name, v)))
+            })
+            .transpose()
+    }
+
+    fn oid_argument(&self, index: usize, command: &str) -> Result<String, CliError> {
+        self.positional
+            .get(index)
+            .cloned()
+            .ok_or_else(|| CliError::Usage(format!("'{}' needs a commit OID", command)))
+    }
+
+    /// Fail on any option that no subcommand consumed.
+    fn finish(self) -> Result<(), CliError> {
+        match self.options.keys().next() {
+            Some(name) => Err(CliError::Usage(format!("Unknown option --{}", name))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_refs(value: &str) -> RefSelection {
+    match value {
+        "head" => RefSelection::Head,
+        "local-branches" => RefSelection::LocalBranches,
+        "all" => RefSelection::All,
+        list => RefSelection::Refs(list.split(',').map(|r| r.trim().to_string()).collect()),
+    }
+}
+
+fn parse_tags(value: &str) -> Result<TagRewrite, CliError> {
+    match value.split_once(':') {
+        None if value == "keep" => Ok(TagRewrite::Keep),
+        None if value == "update" => Ok(TagRewrite::Update),
+        Some(("rename", suffix)) => Ok(TagRewrite::Rename {
+            suffix: suffix.to_string(),
+        }),
+        _ => Err(CliError::Usage(format!(
+            "Invalid value for --tags: {}",
+            value
+        ))),
+    }
+}
+
+/// The `--refs`, `--dry-run`, `--sign`, `--allow-published`, `--auto-stash` and `--tags` options
+/// shared by rewriting commands.
+fn rewrite_options(args: &mut Args) -> Result<RewriteOptions, CliError> {
+    Ok(RewriteOptions {
+        refs: args
+            .take("refs")
+            .map(|r| parse_refs(&r))
+            .unwrap_or_default(),
+        dry_run: args.flag("dry-run"),
+        signing: if args.flag("sign") {
+            SigningMode::Sign
+        } else {
+            SigningMode::Strip
+        },
+        allow_published: args.flag("allow-published"),
+        auto_stash: args.flag("auto-stash"),
+        tags: args
+            .take("tags")
+            .map(|t| parse_tags(&t))
+            .transpose()?
+            .unwrap_or_default(),
+        ..Default::default()
+    })
+}
+
+/// The commit filter options shared by `log` and bulk rewrites.
+fn commit_filters(args: &mut Args) -> Result<CommitFilters, CliError> {
+    Ok(CommitFilters {
+        author_name: args.take("author-name"),
+        author_email: args.take("author-email"),
+        message_pattern: args.take("message"),
+        date_start: args.take_parsed("since")?,
+        date_end: args.take_parsed("until")?,
+        file_path: args.take("path"),
+    })
+}
+
+/// The `--add-trailer`, `--set-trailer` and `--remove-trailer` options.
+fn trailer_edits(args: &mut Args) -> Result<Vec<TrailerEdit>, CliError> {
+    let mut edits = Vec::new();
+    if let Some(trailer) = args.take("add-trailer") {
+        let (key, value) = trailer_argument(&trailer, "add-trailer")?;
+        edits.push(TrailerEdit::Add { key, value });
+    }
+    if let Some(trailer) = args.take("set-trailer") {
+        let (key, value) = trailer_argument(&trailer, "set-trailer")?;
+        edits.push(TrailerEdit::Replace { key, value });
+    }
+    if let Some(key) = args.take("remove-trailer") {
+        edits.push(TrailerEdit::Remove { key, value: None });
+    }
+    Ok(edits)
+}
+
+/// Split a `KEY: VALUE` trailer argument.
+fn trailer_argument(value: &str, flag: &str) -> Result<(String, String), CliError> {
+    match value.split_once(':') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(CliError::Usage(format!("--{} expects 'KEY: VALUE'", flag))),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), CliError> {
+    let json =
+        serde_json::to_string_pretty(value).map_err(|e| engine::Error::Internal(e.to_string()))?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn run(mut args: Args) -> Result<(), CliError> {
+    if args.flag("help") || args.positional.is_empty() {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    let command = args.positional[0].clone();
+    let repo_path = match args.take("repo") {
+        Some(path) => path,
+        None => std::env::current_dir()
+            .map_err(|e| engine::Error::Io {
+                path: ".".to_string(),
+                message: e.to_string(),
+            })?
+            .to_string_lossy()
+            .into_owned(),
+    };
+
+    match command.as_str() {
+        "open" => {
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::repository_info(&repo, &repo_path)?)
+        }
+        "log" => {
+            let offset = args.take_parsed("offset")?.unwrap_or(0);
+            let limit = args.take_parsed("limit")?.unwrap_or(usize::MAX);
+            let filters = commit_filters(&mut args)?;
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::list_commits_filtered(
+                &repo, offset, limit, &filters,
+            )?)
+        }
+        "show" => {
+            let oid = args.oid_argument(1, "show")?;
+            let oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::commit_detail(&repo, oid)?)
+        }
+        "published" => {
+            let oid = args.oid_argument(1, "published")?;
+            let oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::published_refs(&repo, oid)?)
+        }
+        "files" => {
+            let oid = args.oid_argument(1, "files")?;
+            let oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::changed_files(&repo, oid)?)
+        }
+        "diff" => {
+            let oid = args.oid_argument(1, "diff")?;
+            let oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            let path = args
+                .positional
+                .get(2)
+                .cloned()
+                .ok_or_else(|| CliError::Usage("'diff' needs a file path".to_string()))?;
+            let parent_index = args.take_parsed("parent")?.unwrap_or(0);
+            let max_bytes = args
+                .take_parsed("max-bytes")?
+                .unwrap_or(engine::DEFAULT_PATCH_SIZE_LIMIT);
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::file_patch(
+                &repo,
+                oid,
+                &path,
+                parent_index,
+                max_bytes,
+            )?)
+        }
+        "edit" => {
+            let oid = args.oid_argument(1, "edit")?;
+            let target_oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            let message = match (args.take("message"), args.take("message-file")) {
+                (Some(_), Some(_)) => {
+                    return Err(CliError::Usage(
+                        "Use either --message or --message-file".to_string(),
+                    ))
+                }
+                (Some(message), None) => Some(message),
+                (None, Some(file)) => {
+                    Some(
+                        std::fs::read_to_string(&file).map_err(|e| engine::Error::Io {
+                            path: file.clone(),
+                            message: e.to_string(),
+                        })?,
+                    )
+                }
+                (None, None) => None,
+            };
+            let trailers = trailer_edits(&mut args)?;
+            let edit = CommitEdit {
+                author_name: args.take("author-name"),
+                author_email: args.take("author-email"),
+                author_date: args.take_parsed("author-date")?,
+                author_offset: args.take_parsed("author-offset")?,
+                committer_name: args.take("committer-name"),
+                committer_email: args.take("committer-email"),
+                committer_date: args.take_parsed("committer-date")?,
+                committer_offset: args.take_parsed("committer-offset")?,
+                message,
+                trailers,
+            };
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            let result =
+                engine::rewrite_commits(&repo, &[(target_oid, edit)], &options, &|_, _| {})?;
+            print_json(&result)
+        }
+        "trailers" => {
+            let edits = trailer_edits(&mut args)?;
+            if edits.is_empty() {
+                return Err(CliError::Usage(
+                    "'trailers' needs --add-trailer, --set-trailer or --remove-trailer".to_string(),
+                ));
+            }
+            let selection = CommitSelection {
+                range: args.take("range"),
+                filters: Some(commit_filters(&mut args)?),
+            };
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::rewrite_trailers(
+                &repo,
+                &selection,
+                &edits,
+                &options,
+                &|_, _| {},
+            )?)
+        }
+        "dates" => {
+            let applies_to = match args.take("only").as_deref() {
+                None => IdentityRole::Both,
+                Some("author") => IdentityRole::Author,
+                Some("committer") => IdentityRole::Committer,
+                Some(other) => {
+                    return Err(CliError::Usage(format!(
+                        "Invalid value for --only: {}",
+                        other
+                    )))
+                }
+            };
+            let dates = DateRewrite {
+                shift_seconds: args.take_parsed("shift")?.unwrap_or(0),
+                offset_minutes: args.take_parsed("offset")?,
+                applies_to,
+                committer_date_is_author_date: args.flag("committer-date-is-author-date"),
+                require_monotonic: args.flag("require-monotonic"),
+            };
+            let selection = CommitSelection {
+                range: args.take("range"),
+                filters: Some(commit_filters(&mut args)?),
+            };
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::rewrite_dates(
+                &repo,
+                &selection,
+                &dates,
+                &options,
+                &|_, _| {},
+            )?)
+        }
+        "messages" => {
+            let mut rules: Vec<MessageRule> = match args.take("rules") {
+                Some(rules) => serde_json::from_str(&rules)
+                    .map_err(|e| CliError::Usage(format!("Invalid value for --rules: {}", e)))?,
+                None => Vec::new(),
+            };
+            match (args.take("pattern"), args.take("replacement")) {
+                (Some(pattern), Some(replacement)) => rules.push(MessageRule {
+                    pattern,
+                    replacement,
+                }),
+                (None, None) => {}
+                _ => {
+                    return Err(CliError::Usage(
+                        "--pattern and --replacement must be given together".to_string(),
+                    ))
+                }
+            }
+            let selection = CommitSelection {
+                range: args.take("range"),
+                filters: Some(commit_filters(&mut args)?),
+            };
+            let preview = args.flag("preview");
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            if preview {
+                print_json(&engine::preview_message_rules(
+                    &repo,
+                    &selection,
+                    &rules,
+                    &options.refs,
+                )?)
+            } else {
+                print_json(&engine::rewrite_messages(
+                    &repo,
+                    &selection,
+                    &rules,
+                    &options,
+                    &|_, _| {},
+                )?)
+            }
+        }
+        "squash" => {
+            let oids = args.positional[1..]
+                .iter()
+                .map(|oid| engine::parse_oid(oid).map_err(|e| CliError::Usage(e.to_string())))
+                .collect::<Result<Vec<_>, _>>()?;
+            if oids.len() < 2 {
+                return Err(CliError::Usage(
+                    "'squash' needs at least two commit OIDs".to_string(),
+                ));
+            }
+            let squash = SquashOptions {
+                mode: if args.flag("fixup") {
+                    SquashMode::Fixup
+                } else {
+                    SquashMode::Squash
+                },
+                message_template: args.take("template"),
+                message: args.take("message"),
+                author_oid: args.take("author-oid"),
+            };
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::squash_commits(
+                &repo,
+                &oids,
+                &squash,
+                &options,
+                &|_, _| {},
+            )?)
+        }
+        "reorder" => {
+            let new_order = args.positional[1..]
+                .iter()
+                .map(|oid| engine::parse_oid(oid).map_err(|e| CliError::Usage(e.to_string())))
+                .collect::<Result<Vec<_>, _>>()?;
+            if new_order.len() < 2 {
+                return Err(CliError::Usage(
+                    "'reorder' needs at least two commit OIDs".to_string(),
+                ));
+            }
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::reorder_commits(
+                &repo,
+                &new_order,
+                &options,
+                &|_, _| {},
+            )?)
+        }
+        "split" => {
+            let oid = args.oid_argument(1, "split")?;
+            let oid = engine::parse_oid(&oid).map_err(|e| CliError::Usage(e.to_string()))?;
+            let groups = args
+                .take("groups")
+                .ok_or_else(|| CliError::Usage("'split' needs --groups".to_string()))?;
+            let groups: Vec<SplitGroup> = serde_json::from_str(&groups)
+                .map_err(|e| CliError::Usage(format!("Invalid value for --groups: {}", e)))?;
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::split_commit(
+                &repo,
+                oid,
+                &groups,
+                &options,
+                &|_, _| {},
+            )?)
+        }
+        "drop" => {
+            let oids = args.positional[1..]
+                .iter()
+                .map(|oid| engine::parse_oid(oid).map_err(|e| CliError::Usage(e.to_string())))
+                .collect::<Result<Vec<_>, _>>()?;
+            if oids.is_empty() {
+                return Err(CliError::Usage(
+                    "'drop' needs at least one commit OID".to_string(),
+                ));
+            }
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::drop_commits(&repo, &oids, &options, &|_, _| {})?)
+        }
+        "remove-paths" => {
+            let remove = RemovePathsOptions {
+                paths: args.positional[1..].to_vec(),
+                prune_empty: args.flag("prune-empty"),
+            };
+            if remove.paths.is_empty() {
+                return Err(CliError::Usage(
+                    "'remove-paths' needs at least one path".to_string(),
+                ));
+            }
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::remove_paths(&repo, &remove, &options, &|_, _| {})?)
+        }
+        "scrub" => {
+            let texts = args.positional[1..].to_vec();
+            if texts.is_empty() {
+                return Err(CliError::Usage(
+                    "'scrub' needs at least one text to replace".to_string(),
+                ));
+            }
+            let (literals, patterns) = if args.flag("regex") {
+                (Vec::new(), texts)
+            } else {
+                (texts, Vec::new())
+            };
+            let scrub = ScrubOptions {
+                literals,
+                patterns,
+                replacement: args.take("replacement"),
+                include_binary: args.flag("include-binary"),
+            };
+            let options = rewrite_options(&mut args)?;
+            args.finish()?;
+
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::scrub_blobs(&repo, &scrub, &options, &|_, _| {})?)
+        }
+        "backup" => {
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::backup_status(&repo)?)
+        }
+        "backups" => {
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            print_json(&engine::list_backup_entries(&repo)?)
+        }
+        "restore" => {
+            let entry_id = args.positional.get(1).cloned();
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            let oid = engine::undo_rewrite(&repo, entry_id.as_deref())?;
+            print_json(&serde_json::json!({ "restored_oid": oid }))
+        }
+        "redo" => {
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            let oid = engine::redo_rewrite(&repo)?;
+            print_json(&serde_json::json!({ "restored_oid": oid }))
+        }
+        "delete-backup" => {
+            let entry_id =
+                args.positional.get(1).cloned().ok_or_else(|| {
+                    CliError::Usage("'delete-backup' needs an entry ID".to_string())
+                })?;
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            engine::delete_backup_entry(&repo, &entry_id)?;
+            print_json(&serde_json::json!({ "deleted": [entry_id] }))
+        }
+        "prune-backups" => {
+            let older_than = args.take_parsed("older-than")?;
+            let keep_last = args.take_parsed("keep-last")?;
+            let prune = match (older_than, keep_last) {
+                (Some(timestamp), None) => BackupPrune::OlderThan(timestamp),
+                (None, Some(n)) => BackupPrune::KeepLast(n),
+                _ => {
+                    return Err(CliError::Usage(
+                        "'prune-backups' needs one of --older-than or --keep-last".to_string(),
+                    ))
+                }
+            };
+            args.finish()?;
+            let repo = engine::open_repo(&repo_path)?;
+            let deleted = engine::prune_backups(&repo, &prune)?;
+            print_json(&serde_json::json!({ "deleted": deleted }))
+        }
+        other => Err(CliError::Usage(format!("Unknown command '{}'", other))),
+    }
+}
+
+fn main() -> ExitCode {
+    let result = Args::parse(std::env::args().skip(1)).and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Failed(error)) => {
+            eprintln!("{}", serde_json::json!({ "error": error }));
+            ExitCode::from(1)
+        }
+        Err(CliError::Usage(message)) => {
+            eprintln!(
+                "{}",
+                serde_json::json!({ "error": { "kind": "usage", "message": message } })
+            );
+            eprint!("\n{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
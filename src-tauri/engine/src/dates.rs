@@ -0,0 +1,343 @@
+use crate::error::Error;
+use crate::filter::CommitSelection;
+use crate::identity::IdentityRole;
+use crate::rewrite::{
+    execute_rewrite, plan_rewrite, CommitAction, CommitEdit, RewriteOptions, RewriteResult,
+};
+use git2::{Oid, Repository, Time};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Date changes applied to every selected commit. Shifting and offset
+/// conversion run first; `committer_date_is_author_date` then copies the
+/// resulting author date.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DateRewrite {
+    /// Seconds added to each date; negative values move dates back.
+    pub shift_seconds: i64,
+    /// Convert each date to this UTC offset in minutes, keeping the instant.
+    pub offset_minutes: Option<i32>,
+    /// Which dates `shift_seconds` and `offset_minutes` apply to.
+    pub applies_to: IdentityRole,
+    /// Give each commit its author date as committer date, like
+    /// `git rebase --committer-date-is-author-date`.
+    pub committer_date_is_author_date: bool,
+    /// Refuse to rewrite if a commit would get an earlier committer date than
+    /// one of its parents. Commits that were already older than a parent are
+    /// not reported.
+    pub require_monotonic: bool,
+}
+
+/// New (author, committer) dates of one commit.
+type CommitDates = (Time, Time);
+
+fn convert(time: Time, rewrite: &DateRewrite) -> Time {
+    Time::new(
+        time.seconds() + rewrite.shift_seconds,
+        rewrite.offset_minutes.unwrap_or(time.offset_minutes()),
+    )
+}
+
+fn rewritten_dates(commit: &git2::Commit, rewrite: &DateRewrite) -> CommitDates {
+    let mut author = commit.author().when();
+    let mut committer = commit.committer().when();
+    if rewrite.applies_to != IdentityRole::Committer {
+        author = convert(author, rewrite);
+    }
+    if rewrite.applies_to != IdentityRole::Author {
+        committer = convert(committer, rewrite);
+    }
+    if rewrite.committer_date_is_author_date {
+        committer = author;
+    }
+    (author, committer)
+}
+
+fn same_time(a: Time, b: Time) -> bool {
+    a.seconds() == b.seconds() && a.offset_minutes() == b.offset_minutes()
+}
+
+/// Shift dates, convert them to one UTC offset and/or copy author dates to
+/// committer dates for every commit matching `selection`, in a single walk
+/// over the history of `options.refs`. With `require_monotonic` the new dates
+/// are checked before anything is written.
+pub fn rewrite_dates(
+    repo: &Repository,
+    selection: &CommitSelection,
+    rewrite: &DateRewrite,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    if rewrite.shift_seconds == 0
+        && rewrite.offset_minutes.is_none()
+        && !rewrite.committer_date_is_author_date
+    {
+        return Err(Error::invalid_input("dates", "No date change given"));
+    }
+    if let Some(offset) = rewrite.offset_minutes {
+        if offset.abs() >= 24 * 60 {
+            return Err(Error::invalid_input(
+                "offset_minutes",
+                format!("{} minutes is not a valid UTC offset", offset),
+            ));
+        }
+    }
+    let matcher = selection.matcher(repo)?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let mut new_dates: HashMap<Oid, CommitDates> = HashMap::new();
+    for oid in &plan.oids {
+        let commit = repo.find_commit(*oid)?;
+        if !matcher.matches(repo, &commit)? {
+            continue;
+        }
+        let (author, committer) = rewritten_dates(&commit, rewrite);
+        if !same_time(author, commit.author().when())
+            || !same_time(committer, commit.committer().when())
+        {
+            new_dates.insert(*oid, (author, committer));
+        }
+    }
+    if new_dates.is_empty() {
+        return Err(Error::nothing_to_rewrite(
+            "No selected commit changes its dates",
+        ));
+    }
+    if rewrite.require_monotonic {
+        check_monotonic(repo, &plan.oids, &new_dates)?;
+    }
+
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            let Some((author, committer)) = new_dates.get(&commit.id()) else {
+                return Ok(CommitAction::Keep);
+            };
+            Ok(CommitAction::Edit {
+                edit: CommitEdit {
+                    author_date: Some(author.seconds()),
+                    author_offset: Some(author.offset_minutes()),
+                    committer_date: Some(committer.seconds()),
+                    committer_offset: Some(committer.offset_minutes()),
+                    ..Default::default()
+                },
+                tree: None,
+            })
+        },
+        "rewrote dates",
+        options,
+        on_progress,
+    )?;
+    outcome.into_result(None, options.dry_run)
+}
+
+/// Fail on the first commit whose new committer date is earlier than a
+/// parent's new one, unless it was already earlier before the rewrite.
+fn check_monotonic(
+    repo: &Repository,
+    oids: &[Oid],
+    new_dates: &HashMap<Oid, CommitDates>,
+) -> Result<(), Error> {
+    let committer_seconds = |commit: &git2::Commit| {
+        let original = commit.committer().when().seconds();
+        let new = new_dates
+            .get(&commit.id())
+            .map_or(original, |(_, committer)| committer.seconds());
+        (original, new)
+    };
+    for oid in oids {
+        let commit = repo.find_commit(*oid)?;
+        let (original, new) = committer_seconds(&commit);
+        for parent in commit.parents() {
+            let (parent_original, parent_new) = committer_seconds(&parent);
+            if new < parent_new && original >= parent_original {
+                return Err(Error::NonMonotonicDate {
+                    oid: oid.to_string(),
+                    parent_oid: parent.id().to_string(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::CommitFilters;
+    use git2::Signature;
+
+    /// Three commits with author dates 1000, 2000, 3000 and committer dates
+    /// 500 seconds later, all at UTC+01:00.
+    fn dated_repo() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = crate::test_support::create_test_repo(0);
+        let tree_oid = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree_oid).unwrap();
+        let mut parent: Option<git2::Commit> = None;
+        for i in 1..=3 {
+            let author = Signature::new("A", "a@example.com", &Time::new(i * 1000, 60)).unwrap();
+            let committer =
+                Signature::new("C", "c@example.com", &Time::new(i * 1000 + 500, 60)).unwrap();
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("refs/heads/main"),
+                    &author,
+                    &committer,
+                    &format!("Commit {}", i),
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+            parent = Some(repo.find_commit(oid).unwrap());
+        }
+        drop((tree, parent));
+        repo.set_head("refs/heads/main").unwrap();
+        (dir, repo)
+    }
+
+    /// (author seconds, author offset, committer seconds, committer offset), newest first.
+    fn dates(repo: &Repository) -> Vec<(i64, i32, i64, i32)> {
+        let mut revwalk = repo.revwalk().unwrap();
+        revwalk.push_head().unwrap();
+        revwalk
+            .map(|oid| {
+                let commit = repo.find_commit(oid.unwrap()).unwrap();
+                let (author, committer) = (commit.author().when(), commit.committer().when());
+                (
+                    author.seconds(),
+                    author.offset_minutes(),
+                    committer.seconds(),
+                    committer.offset_minutes(),
+                )
+            })
+            .collect()
+    }
+
+    fn selecting(message: &str) -> CommitSelection {
+        CommitSelection {
+            range: None,
+            filters: Some(CommitFilters {
+                message_pattern: Some(message.to_string()),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_shift_and_convert_offsets() {
+        let (_dir, repo) = dated_repo();
+        let rewrite = DateRewrite {
+            shift_seconds: 3600,
+            applies_to: IdentityRole::Author,
+            ..Default::default()
+        };
+        rewrite_dates(
+            &repo,
+            &CommitSelection::default(),
+            &rewrite,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(dates(&repo)[2], (4600, 60, 1500, 60));
+
+        let rewrite = DateRewrite {
+            offset_minutes: Some(-300),
+            ..Default::default()
+        };
+        rewrite_dates(
+            &repo,
+            &CommitSelection::default(),
+            &rewrite,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            dates(&repo),
+            [
+                (6600, -300, 3500, -300),
+                (5600, -300, 2500, -300),
+                (4600, -300, 1500, -300)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_committer_date_is_author_date() {
+        let (_dir, repo) = dated_repo();
+        let rewrite = DateRewrite {
+            committer_date_is_author_date: true,
+            require_monotonic: true,
+            ..Default::default()
+        };
+        rewrite_dates(
+            &repo,
+            &selecting("commit 2"),
+            &rewrite,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            dates(&repo),
+            [
+                (3000, 60, 3500, 60),
+                (2000, 60, 2000, 60),
+                (1000, 60, 1500, 60)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_require_monotonic_rejects_reordered_dates() {
+        let (_dir, repo) = dated_repo();
+        let head = repo.head().unwrap().target();
+        let rewrite = DateRewrite {
+            shift_seconds: 5000,
+            require_monotonic: true,
+            ..Default::default()
+        };
+
+        let err = rewrite_dates(
+            &repo,
+            &selecting("commit 2"),
+            &rewrite,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "non_monotonic_date");
+        assert_eq!(err.oid(), head.map(|oid| oid.to_string()).as_deref());
+        assert_eq!(repo.head().unwrap().target(), head);
+
+        // Shifting everything keeps the order
+        rewrite_dates(
+            &repo,
+            &CommitSelection::default(),
+            &rewrite,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(dates(&repo)[0], (8000, 60, 8500, 60));
+
+        let err = rewrite_dates(
+            &repo,
+            &CommitSelection::default(),
+            &DateRewrite {
+                offset_minutes: Some(24 * 60),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.field(), Some("offset_minutes"));
+    }
+}
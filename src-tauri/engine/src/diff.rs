@@ -0,0 +1,363 @@
+use crate::error::Error;
+use git2::{Delta, Diff, DiffDelta, DiffFindOptions, DiffLineType, Oid, Patch, Repository};
+use serde::Serialize;
+
+/// Patches larger than this are cut off unless the caller asks for another limit.
+pub const DEFAULT_PATCH_SIZE_LIMIT: usize = 256 * 1024;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+    TypeChanged,
+}
+
+/// One file changed by a commit relative to one of its parents.
+#[derive(Serialize, Clone, Debug)]
+pub struct FileChange {
+    pub status: FileStatus,
+    /// `None` for added files.
+    pub old_path: Option<String>,
+    /// `None` for deleted files.
+    pub new_path: Option<String>,
+    /// Rename/copy similarity in percent.
+    pub similarity: Option<u16>,
+    pub is_binary: bool,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// The files a commit changes against one parent; `parent_oid` is `None`
+/// for a root commit, which is compared with the empty tree.
+#[derive(Serialize, Clone, Debug)]
+pub struct ParentDiff {
+    pub parent_oid: Option<String>,
+    pub files: Vec<FileChange>,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FilePatch {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub status: FileStatus,
+    pub is_binary: bool,
+    /// Unified diff text; empty for binary files.
+    pub patch: String,
+    /// The patch was cut off at the size limit.
+    pub truncated: bool,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// Diff of `commit` against its `parent_index`-th parent (or the empty tree),
+/// with rename and copy detection applied.
+fn diff_against_parent<'r>(
+    repo: &'r Repository,
+    commit: &git2::Commit,
+    parent_index: usize,
+) -> Result<Diff<'r>, Error> {
+    let tree = commit.tree()?;
+    let parent_tree = if commit.parent_count() == 0 {
+        None
+    } else {
+        Some(commit.parent(parent_index)?.tree()?)
+    };
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+    Ok(diff)
+}
+
+fn file_status(status: Delta) -> FileStatus {
+    match status {
+        Delta::Added => FileStatus::Added,
+        Delta::Deleted => FileStatus::Deleted,
+        Delta::Renamed => FileStatus::Renamed,
+        Delta::Copied => FileStatus::Copied,
+        Delta::Typechange => FileStatus::TypeChanged,
+        _ => FileStatus::Modified,
+    }
+}
+
+fn path_string(path: Option<&std::path::Path>) -> Option<String> {
+    path.map(|p| p.to_string_lossy().into_owned())
+}
+
+fn file_change(diff: &Diff, idx: usize) -> Result<FileChange, Error> {
+    let delta = diff
+        .get_delta(idx)
+        .ok_or_else(|| Error::Internal("Diff delta out of range".to_string()))?;
+    let status = file_status(delta.status());
+    // Generating the patch is what decides whether the file is binary
+    let patch = Patch::from_diff(diff, idx)?;
+    let is_binary = delta.flags().is_binary() || patch.is_none();
+    let (additions, deletions) = match &patch {
+        Some(patch) if !is_binary => {
+            let (_, additions, deletions) = patch.line_stats()?;
+            (additions, deletions)
+        }
+        _ => (0, 0),
+    };
+
+    Ok(FileChange {
+        status,
+        old_path: match status {
+            FileStatus::Added => None,
+            _ => path_string(delta.old_file().path()),
+        },
+        new_path: match status {
+            FileStatus::Deleted => None,
+            _ => path_string(delta.new_file().path()),
+        },
+        similarity: match status {
+            FileStatus::Renamed | FileStatus::Copied => similarity(&delta, patch),
+            _ => None,
+        },
+        is_binary,
+        additions,
+        deletions,
+    })
+}
+
+/// Similarity of a rename or copy in percent. An unchanged blob is an exact
+/// match. Otherwise the score `find_similar` computed is needed, which git2
+/// does not expose on `DiffDelta`; it is taken from the file header of the
+/// patch, without printing any of its hunks.
+fn similarity(delta: &DiffDelta, patch: Option<Patch>) -> Option<u16> {
+    if delta.old_file().id() == delta.new_file().id() {
+        return Some(100);
+    }
+    let mut header = Vec::new();
+    // Stopping at the first hunk makes `print` report an error
+    let _ = patch?.print(&mut |_, _, line| {
+        if line.origin_value() != DiffLineType::FileHeader {
+            return false;
+        }
+        header.extend_from_slice(line.content());
+        true
+    });
+    std::str::from_utf8(&header)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("similarity index "))
+        .and_then(|rest| rest.trim_end_matches('%').parse().ok())
+}
+
+/// The files changed by a commit against each of its parents.
+pub fn changed_files(repo: &Repository, oid: Oid) -> Result<Vec<ParentDiff>, Error> {
+    let commit = repo.find_commit(oid)?;
+    let parents: Vec<Option<Oid>> = if commit.parent_count() == 0 {
+        vec![None]
+    } else {
+        commit.parent_ids().map(Some).collect()
+    };
+
+    parents
+        .into_iter()
+        .enumerate()
+        .map(|(parent_index, parent_oid)| {
+            let diff = diff_against_parent(repo, &commit, parent_index)?;
+            let files = (0..diff.deltas().len())
+                .map(|idx| file_change(&diff, idx))
+                .collect::<Result<Vec<_>, Error>>()?;
+            Ok(ParentDiff {
+                parent_oid: parent_oid.map(|o| o.to_string()),
+                additions: files.iter().map(|f| f.additions).sum(),
+                deletions: files.iter().map(|f| f.deletions).sum(),
+                files,
+            })
+        })
+        .collect()
+}
+
+/// Unified patch for one file of a commit, against its `parent_index`-th
+/// parent. `path` may be either side of a rename. Patches longer than
+/// `max_bytes` are only printed up to the last full line before the limit.
+pub fn file_patch(
+    repo: &Repository,
+    oid: Oid,
+    path: &str,
+    parent_index: usize,
+    max_bytes: usize,
+) -> Result<FilePatch, Error> {
+    let commit = repo.find_commit(oid)?;
+    if parent_index > 0 && parent_index >= commit.parent_count() {
+        return Err(Error::invalid_input(
+            "parent_index",
+            format!("Commit {} has no parent #{}", oid, parent_index),
+        ));
+    }
+    let diff = diff_against_parent(repo, &commit, parent_index)?;
+    let idx = diff
+        .deltas()
+        .position(|delta| {
+            [delta.new_file().path(), delta.old_file().path()]
+                .iter()
+                .flatten()
+                .any(|p| p.to_string_lossy() == path)
+        })
+        .ok_or_else(|| {
+            Error::invalid_input(
+                "path",
+                format!("'{}' is not changed by commit {}", path, oid),
+            )
+        })?;
+
+    let change = file_change(&diff, idx)?;
+    let mut text = Vec::new();
+    let mut truncated = false;
+    if let Some(mut patch) = Patch::from_diff(&diff, idx)?.filter(|_| !change.is_binary) {
+        // Print line by line and stop before the line that would exceed the limit
+        let printed = patch.print(&mut |_, _, line| {
+            let origin = matches!(
+                line.origin_value(),
+                DiffLineType::Context | DiffLineType::Addition | DiffLineType::Deletion
+            );
+            if text.len() + usize::from(origin) + line.content().len() > max_bytes {
+                // A file header spans several lines; keep the ones that fit
+                if !origin {
+                    let fits = &line.content()[..max_bytes - text.len()];
+                    let cut = fits.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+                    text.extend_from_slice(&fits[..cut]);
+                }
+                truncated = true;
+                return false;
+            }
+            if origin {
+                text.push(line.origin() as u8);
+            }
+            text.extend_from_slice(line.content());
+            true
+        });
+        if !truncated {
+            printed?;
+        }
+    }
+    let patch = String::from_utf8_lossy(&text).into_owned();
+
+    Ok(FilePatch {
+        old_path: change.old_path,
+        new_path: change.new_path,
+        status: change.status,
+        is_binary: change.is_binary,
+        patch,
+        truncated,
+        additions: change.additions,
+        deletions: change.deletions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_files, create_test_repo};
+    use std::path::Path;
+
+    #[test]
+    fn test_changed_files_with_stats_and_renames() {
+        let (_dir, repo) = create_test_repo(2);
+        let text = b"line one\nline two\nline three\nline four\n";
+        let draft = b"alpha\nbeta\ngamma\ndelta\nepsilon\nzeta\neta\ntheta\n";
+        commit_files(
+            &repo,
+            &[("notes.txt", Some(text)), ("draft.txt", Some(draft))],
+            "Add notes",
+        );
+        let oid = commit_files(
+            &repo,
+            &[
+                ("notes.txt", None),
+                ("renamed.txt", Some(text)),
+                ("draft.txt", None),
+                (
+                    "final.txt",
+                    Some(b"alpha\nbeta\ngamma\ndelta\nepsilon\nzeta\neta\nomega\n"),
+                ),
+                ("file_0.txt", Some(b"changed\nand added\n")),
+                ("image.bin", Some(&[0u8, 159, 146, 150, 0, 1])),
+            ],
+            "Mixed changes",
+        );
+
+        let diffs = changed_files(&repo, oid).unwrap();
+        assert_eq!(diffs.len(), 1);
+        let files = &diffs[0].files;
+        let find = |path: &str| {
+            files
+                .iter()
+                .find(|f| f.new_path.as_deref() == Some(path))
+                .unwrap()
+        };
+
+        let renamed = find("renamed.txt");
+        assert_eq!(renamed.status, FileStatus::Renamed);
+        assert_eq!(renamed.old_path.as_deref(), Some("notes.txt"));
+        assert_eq!(renamed.similarity, Some(100));
+        let edited = find("final.txt");
+        assert_eq!(edited.status, FileStatus::Renamed);
+        assert!(matches!(edited.similarity, Some(score) if (50..100).contains(&score)));
+
+        let modified = find("file_0.txt");
+        assert_eq!(modified.status, FileStatus::Modified);
+        assert_eq!((modified.additions, modified.deletions), (2, 1));
+
+        let binary = find("image.bin");
+        assert_eq!(binary.status, FileStatus::Added);
+        assert!(binary.is_binary);
+        assert_eq!(files.len(), 4);
+    }
+
+    #[test]
+    fn test_changed_files_root_commit() {
+        let (_dir, repo) = create_test_repo(1);
+        let root = repo.head().unwrap().target().unwrap();
+        let diffs = changed_files(&repo, root).unwrap();
+        assert_eq!(diffs[0].parent_oid, None);
+        assert_eq!(diffs[0].files[0].status, FileStatus::Added);
+        assert_eq!(diffs[0].files[0].new_path.as_deref(), Some("file_0.txt"));
+    }
+
+    #[test]
+    fn test_file_patch_truncates_and_skips_binary() {
+        let (_dir, repo) = create_test_repo(1);
+        let long: String = (0..200).map(|i| format!("line {}\n", i)).collect();
+        let oid = commit_files(
+            &repo,
+            &[
+                ("long.txt", Some(long.as_bytes())),
+                ("blob.bin", Some(&[0u8, 1, 2, 0])),
+            ],
+            "Add files",
+        );
+
+        let full = file_patch(&repo, oid, "long.txt", 0, DEFAULT_PATCH_SIZE_LIMIT).unwrap();
+        assert!(!full.truncated);
+        assert!(full.patch.contains("+line 199\n"));
+        assert_eq!(full.additions, 200);
+        let diff = diff_against_parent(&repo, &repo.find_commit(oid).unwrap(), 0).unwrap();
+        let idx = diff
+            .deltas()
+            .position(|d| d.new_file().path() == Some(Path::new("long.txt")));
+        let mut patch = Patch::from_diff(&diff, idx.unwrap()).unwrap().unwrap();
+        assert_eq!(full.patch.as_bytes(), &*patch.to_buf().unwrap());
+
+        let cut = file_patch(&repo, oid, "long.txt", 0, 100).unwrap();
+        assert!(cut.truncated);
+        assert!(cut.patch.len() <= 100);
+        assert!(cut.patch.ends_with('\n'));
+
+        let binary = file_patch(&repo, oid, "blob.bin", 0, DEFAULT_PATCH_SIZE_LIMIT).unwrap();
+        assert!(binary.is_binary);
+        assert!(binary.patch.is_empty());
+
+        let missing = file_patch(&repo, oid, "file_0.txt", 0, DEFAULT_PATCH_SIZE_LIMIT)
+            .err()
+            .unwrap();
+        assert_eq!(missing.field(), Some("path"));
+    }
+}
@@ -0,0 +1,254 @@
+use crate::error::Error;
+use crate::rewrite::{
+    execute_rewrite, merge_tree_changes, plan_rewrite, CommitAction, RefSelection, RewriteOptions,
+    RewriteResult,
+};
+use git2::{Oid, Repository};
+use std::collections::{HashMap, HashSet};
+
+/// Remove non-merge commits from history. Every descendant is replayed onto
+/// the rewritten history with an in-memory three-way merge that takes the
+/// dropped changes back out of its tree; if any of them conflicts the whole
+/// operation fails with `MergeConflict` before a single ref is written.
+///
+/// A commit whose rewritten parent ends up with the same tree as its original
+/// parent keeps its own tree without merging. That makes dropping a commit
+/// together with its revert, or an empty commit, a pure re-parenting, and
+/// stops the replay at the first commit past a later revert of the dropped
+/// changes.
+///
+/// `old_oid` on the result is the oldest dropped commit and `new_oid` the
+/// commit that takes its place (empty when a root commit was dropped); every
+/// dropped commit is listed in `dropped_commits`.
+pub fn drop_commits(
+    repo: &Repository,
+    oids: &[Oid],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let plan = plan_rewrite(repo, &options.refs)?;
+    if oids.is_empty() {
+        return Err(Error::invalid_input(
+            "oids",
+            "Select at least one commit to drop",
+        ));
+    }
+    let mut to_drop: HashSet<Oid> = HashSet::new();
+    for oid in oids {
+        if !to_drop.insert(*oid) {
+            return Err(Error::DuplicateEdit {
+                oid: oid.to_string(),
+            });
+        }
+        if !plan.oids.contains(oid) {
+            return Err(Error::CommitNotInHistory {
+                oid: oid.to_string(),
+                head_only: matches!(options.refs, RefSelection::Head),
+            });
+        }
+        if repo.find_commit(*oid)?.parent_count() > 1 {
+            return Err(Error::invalid_input(
+                "oids",
+                format!("Cannot drop merge commit {}", oid),
+            ));
+        }
+    }
+
+    let empty_tree = repo.treebuilder(None)?.write()?;
+    // Original commit -> tree of the commit that replaces it in the new history
+    let mut new_trees: HashMap<Oid, Oid> = HashMap::new();
+    let description = match oids.len() {
+        1 => format!("dropped commit {}", &oids[0].to_string()[..8]),
+        n => format!("dropped {} commits", n),
+    };
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            if to_drop.contains(&commit.id()) {
+                let replacement = match commit.parent_ids().next() {
+                    Some(parent) => match new_trees.get(&parent) {
+                        Some(tree) => *tree,
+                        None => repo.find_commit(parent)?.tree_id(),
+                    },
+                    None => empty_tree,
+                };
+                new_trees.insert(commit.id(), replacement);
+                return Ok(CommitAction::Drop);
+            }
+
+            let mut tree = commit.tree()?;
+            for parent in commit.parents() {
+                let Some(new_parent_tree) = new_trees.get(&parent.id()).copied() else {
+                    continue;
+                };
+                if new_parent_tree == parent.tree_id() {
+                    continue;
+                }
+                let merged = merge_tree_changes(
+                    repo,
+                    commit.id(),
+                    &parent.tree()?,
+                    &repo.find_tree(new_parent_tree)?,
+                    &tree,
+                )?;
+                tree = repo.find_tree(merged)?;
+            }
+            new_trees.insert(commit.id(), tree.id());
+            Ok(if tree.id() == commit.tree_id() {
+                CommitAction::Keep
+            } else {
+                CommitAction::Edit {
+                    edit: Default::default(),
+                    tree: Some(tree.id()),
+                }
+            })
+        },
+        &description,
+        options,
+        on_progress,
+    )?;
+
+    // The oldest dropped commit is replaced by its nearest kept ancestor
+    let oldest = *plan.oids.iter().find(|oid| to_drop.contains(oid)).unwrap();
+    let mut replacement = repo.find_commit(oldest)?.parent_ids().next();
+    while let Some(parent) = replacement.filter(|p| to_drop.contains(p)) {
+        replacement = repo.find_commit(parent)?.parent_ids().next();
+    }
+    let new_oid = replacement
+        .map(|p| outcome.oid_map.get(&p).copied().unwrap_or(p).to_string())
+        .unwrap_or_default();
+    Ok(outcome.into_result_for(oldest.to_string(), new_oid, options.dry_run))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::list_backup_entries;
+    use crate::repo::list_commits;
+    use crate::test_support::{commit_files, create_test_repo};
+    use std::path::Path;
+
+    fn messages(repo: &Repository) -> Vec<String> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.short_message)
+            .collect()
+    }
+
+    fn oids_newest_first(repo: &Repository) -> Vec<Oid> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .iter()
+            .map(|c| Oid::from_str(&c.oid).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_replays_descendants_without_the_changes() {
+        let (_dir, repo) = create_test_repo(4);
+        let oids = oids_newest_first(&repo);
+
+        let result =
+            drop_commits(&repo, &[oids[2]], &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.old_oid, oids[2].to_string());
+        assert_eq!(result.new_oid, oids[3].to_string());
+        assert_eq!(result.dropped_commits, vec![oids[2].to_string()]);
+        assert_eq!(messages(&repo), ["Commit 3", "Commit 2", "Commit 0"]);
+
+        let tip_tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(tip_tree.get_path(Path::new("file_1.txt")).is_err());
+        assert!(tip_tree.get_path(Path::new("file_3.txt")).is_ok());
+        let tip_change = result
+            .commits
+            .iter()
+            .find(|c| c.old_oid == oids[0].to_string())
+            .unwrap();
+        assert_eq!(tip_change.changed_fields, ["tree", "parents"]);
+    }
+
+    #[test]
+    fn test_drop_conflict_leaves_refs_untouched() {
+        let (_dir, repo) = create_test_repo(1);
+        let first = commit_files(
+            &repo,
+            &[("file_0.txt", Some(b"first edit\n"))],
+            "First edit",
+        );
+        let second = commit_files(
+            &repo,
+            &[("file_0.txt", Some(b"second edit\n"))],
+            "Second edit",
+        );
+
+        let err = drop_commits(&repo, &[first], &RewriteOptions::default(), &|_, _| {})
+            .err()
+            .unwrap();
+        match &err {
+            Error::MergeConflict { oid, paths } => {
+                assert_eq!(oid, &second.to_string());
+                assert_eq!(paths, &["file_0.txt"]);
+            }
+            other => panic!("expected a merge conflict, got {:?}", other),
+        }
+        assert_eq!(repo.head().unwrap().target(), Some(second));
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_drop_reverted_changes_only_reparents() {
+        let (_dir, repo) = create_test_repo(1);
+        let change = commit_files(&repo, &[("file_0.txt", Some(b"changed\n"))], "Change");
+        let other = commit_files(&repo, &[("other.txt", Some(b"other\n"))], "Other");
+        let revert = commit_files(
+            &repo,
+            &[("file_0.txt", Some(b"content 0"))],
+            "Revert change",
+        );
+        let after = commit_files(&repo, &[("after.txt", Some(b"after\n"))], "After");
+        let tip_tree = repo.find_commit(after).unwrap().tree_id();
+
+        // Dropping the change alone replays "Other"; the revert ends up empty with its
+        // original tree, so nothing above it is merged
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let preview = drop_commits(&repo, &[change], &dry, &|_, _| {}).unwrap();
+        let fields = |oid: Oid| {
+            preview
+                .commits
+                .iter()
+                .find(|c| c.old_oid == oid.to_string())
+                .unwrap()
+                .changed_fields
+                .clone()
+        };
+        assert_eq!(fields(other), ["tree", "parents"]);
+        assert_eq!(fields(revert), ["parents"]);
+        assert_eq!(fields(after), ["parents"]);
+        assert_eq!(repo.head().unwrap().target(), Some(after));
+
+        // Dropped together with its revert, only "Other" in between is merged
+        let result = drop_commits(
+            &repo,
+            &[revert, change],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            result.dropped_commits,
+            vec![change.to_string(), revert.to_string()]
+        );
+        let fields: Vec<&[String]> = result
+            .commits
+            .iter()
+            .map(|c| c.changed_fields.as_slice())
+            .collect();
+        assert_eq!(fields, [&["tree", "parents"][..], &["parents"][..]]);
+        assert_eq!(messages(&repo), ["After", "Other", "Commit 0"]);
+        assert_eq!(repo.head().unwrap().peel_to_tree().unwrap().id(), tip_tree);
+    }
+}
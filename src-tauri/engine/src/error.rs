@@ -0,0 +1,483 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::path::Path;
+
+/// The libgit2 error behind a failure, as reported by git2.
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct GitErrorInfo {
+    /// `git2::ErrorClass`, e.g. `Reference` or `Os`.
+    pub class: String,
+    /// `git2::ErrorCode`, e.g. `NotFound` or `Locked`.
+    pub code: String,
+    pub raw_code: i32,
+    pub message: String,
+}
+
+impl From<&git2::Error> for GitErrorInfo {
+    fn from(e: &git2::Error) -> Self {
+        GitErrorInfo {
+            class: format!("{:?}", e.class()),
+            code: format!("{:?}", e.code()),
+            raw_code: e.raw_code(),
+            message: e.message().to_string(),
+        }
+    }
+}
+
+/// Everything the engine can fail with.
+///
+/// Serializes to `{ kind, message, field?, oid?, parent_oid?, ref_name?, paths?, refs?,
+/// unapplied_stash?, git? }`, where
+/// `kind` is the snake_case variant name, so the UI and scripts can react to
+/// the kind of failure instead of parsing `message`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    NotARepository {
+        path: String,
+        git: GitErrorInfo,
+    },
+    /// HEAD does not point to a branch; `action` is what could not be done.
+    DetachedHead {
+        action: String,
+    },
+    RefNotFound {
+        ref_name: String,
+    },
+    /// The commit is not reachable from the refs being rewritten.
+    CommitNotInHistory {
+        oid: String,
+        head_only: bool,
+    },
+    InvalidOid {
+        value: String,
+        git: GitErrorInfo,
+    },
+    /// A name or email rejected by git; `field` is `author` or `committer`.
+    InvalidSignature {
+        field: String,
+        git: GitErrorInfo,
+    },
+    /// A request argument is unusable; `field` names it where possible.
+    InvalidInput {
+        field: Option<String>,
+        message: String,
+    },
+    /// The same commit was given more than one edit.
+    DuplicateEdit {
+        oid: String,
+    },
+    /// The request selects nothing to rewrite.
+    NothingToRewrite {
+        message: String,
+    },
+    SigningFailed {
+        oid: String,
+        message: String,
+    },
+    /// Replaying `oid` onto its new parent conflicts in `paths`; nothing was changed.
+    MergeConflict {
+        oid: String,
+        paths: Vec<String>,
+    },
+    /// `oid` is already contained in remote-tracking refs or tags (`refs`) and
+    /// the rewrite did not set `allow_published`; nothing was changed.
+    AlreadyPublished {
+        oid: String,
+        refs: Vec<String>,
+    },
+    /// Something else moved `ref_name` while it was being rewritten or
+    /// restored, so it was left alone. Rewritten commits stay reachable from
+    /// `recovery_refs`.
+    RefMoved {
+        ref_name: String,
+        recovery_refs: Vec<String>,
+    },
+    /// Local changes to `paths` would be overwritten when the working tree is
+    /// updated to the rewritten HEAD; nothing was changed. If auto-stashed
+    /// changes could not be re-applied, they stay in stash `unapplied_stash`.
+    DirtyWorktree {
+        paths: Vec<String>,
+        unapplied_stash: Option<String>,
+    },
+    /// The new committer date of `oid` would be earlier than its parent's.
+    NonMonotonicDate {
+        oid: String,
+        parent_oid: String,
+    },
+    PermissionDenied {
+        path: Option<String>,
+        git: GitErrorInfo,
+    },
+    RefUpdateFailed {
+        ref_name: String,
+        git: GitErrorInfo,
+    },
+    /// No backup entry with this id, or (`None`) none to undo for the branch.
+    BackupNotFound {
+        entry_id: Option<String>,
+    },
+    NothingToRedo,
+    Io {
+        path: String,
+        message: String,
+    },
+    Git(GitErrorInfo),
+    /// An invariant of the engine itself was violated.
+    Internal(String),
+}
+
+impl Error {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::NotARepository { .. } => "not_a_repository",
+            Error::DetachedHead { .. } => "detached_head",
+            Error::RefNotFound { .. } => "ref_not_found",
+            Error::CommitNotInHistory { .. } => "commit_not_in_history",
+            Error::InvalidOid { .. } => "invalid_oid",
+            Error::InvalidSignature { .. } => "invalid_signature",
+            Error::InvalidInput { .. } => "invalid_input",
+            Error::DuplicateEdit { .. } => "duplicate_edit",
+            Error::NothingToRewrite { .. } => "nothing_to_rewrite",
+            Error::SigningFailed { .. } => "signing_failed",
+            Error::MergeConflict { .. } => "merge_conflict",
+            Error::AlreadyPublished { .. } => "already_published",
+            Error::RefMoved { .. } => "ref_moved",
+            Error::DirtyWorktree { .. } => "dirty_worktree",
+            Error::NonMonotonicDate { .. } => "non_monotonic_date",
+            Error::PermissionDenied { .. } => "permission_denied",
+            Error::RefUpdateFailed { .. } => "ref_update_failed",
+            Error::BackupNotFound { .. } => "backup_not_found",
+            Error::NothingToRedo => "nothing_to_redo",
+            Error::Io { .. } => "io",
+            Error::Git(_) => "git",
+            Error::Internal(_) => "internal",
+        }
+    }
+
+    /// The request field the error is about, if any.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Error::InvalidSignature { field, .. } => Some(field),
+            Error::InvalidInput { field, .. } => field.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The commit the error is about, if any.
+    pub fn oid(&self) -> Option<&str> {
+        match self {
+            Error::CommitNotInHistory { oid, .. }
+            | Error::DuplicateEdit { oid }
+            | Error::SigningFailed { oid, .. }
+            | Error::MergeConflict { oid, .. }
+            | Error::AlreadyPublished { oid, .. }
+            | Error::NonMonotonicDate { oid, .. } => Some(oid),
+            Error::InvalidOid { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The parent a `NonMonotonicDate` commit would predate.
+    pub fn parent_oid(&self) -> Option<&str> {
+        match self {
+            Error::NonMonotonicDate { parent_oid, .. } => Some(parent_oid),
+            _ => None,
+        }
+    }
+
+    pub fn ref_name(&self) -> Option<&str> {
+        match self {
+            Error::RefNotFound { ref_name }
+            | Error::RefUpdateFailed { ref_name, .. }
+            | Error::RefMoved { ref_name, .. } => Some(ref_name),
+            _ => None,
+        }
+    }
+
+    /// The conflicting paths of a `MergeConflict` or `DirtyWorktree`.
+    pub fn paths(&self) -> Option<&[String]> {
+        match self {
+            Error::MergeConflict { paths, .. } | Error::DirtyWorktree { paths, .. } => Some(paths),
+            _ => None,
+        }
+    }
+
+    /// The refs that already contain the commit of an `AlreadyPublished`, or
+    /// the recovery refs of a `RefMoved`.
+    pub fn refs(&self) -> Option<&[String]> {
+        match self {
+            Error::AlreadyPublished { refs, .. } => Some(refs),
+            Error::RefMoved { recovery_refs, .. } if !recovery_refs.is_empty() => {
+                Some(recovery_refs)
+            }
+            _ => None,
+        }
+    }
+
+    /// The auto-stash entry a `DirtyWorktree` left in the stash list.
+    pub fn unapplied_stash(&self) -> Option<&str> {
+        match self {
+            Error::DirtyWorktree {
+                unapplied_stash, ..
+            } => unapplied_stash.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn git(&self) -> Option<&GitErrorInfo> {
+        match self {
+            Error::NotARepository { git, .. }
+            | Error::InvalidOid { git, .. }
+            | Error::InvalidSignature { git, .. }
+            | Error::PermissionDenied { git, .. }
+            | Error::RefUpdateFailed { git, .. }
+            | Error::Git(git) => Some(git),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn invalid_input(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Error::InvalidInput {
+            field: Some(field.into()),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn nothing_to_rewrite(message: impl Into<String>) -> Self {
+        Error::NothingToRewrite {
+            message: message.into(),
+        }
+    }
+
+    /// Classify a failed ref write, telling permission problems apart.
+    pub(crate) fn ref_update(repo: &git2::Repository, ref_name: &str, e: git2::Error) -> Self {
+        // The ref file, or the directory its lock file is created in
+        let ref_path = repo.commondir().join(ref_name);
+        let denied = [Some(ref_path.as_path()), ref_path.parent()]
+            .into_iter()
+            .flatten()
+            .any(|path| access_denied(&e, path, true));
+        let git = GitErrorInfo::from(&e);
+        if denied {
+            Error::PermissionDenied {
+                path: Some(ref_name.to_string()),
+                git,
+            }
+        } else {
+            Error::RefUpdateFailed {
+                ref_name: ref_name.to_string(),
+                git,
+            }
+        }
+    }
+}
+
+/// Whether `e` failed because the OS denied access to `path`. libgit2 has no
+/// error code for permission problems, so for an `Os` class error the path is
+/// checked directly; with `write` a read-only path also counts as denied.
+pub(crate) fn access_denied(e: &git2::Error, path: &Path, write: bool) -> bool {
+    if e.class() != git2::ErrorClass::Os {
+        return false;
+    }
+    let access = match std::fs::metadata(path) {
+        Ok(metadata) if write && metadata.permissions().readonly() => return true,
+        Ok(metadata) if metadata.is_dir() => std::fs::read_dir(path).map(drop),
+        Ok(_) => std::fs::File::open(path).map(drop),
+        Err(e) => Err(e),
+    };
+    matches!(access, Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotARepository { path, .. } => write!(
+                f,
+                "'{}' is not a Git repository. Select a folder that contains a .git directory.",
+                path
+            ),
+            Error::DetachedHead { action } => {
+                write!(f, "Cannot {}: HEAD is detached. Please check out a branch first.", action)
+            }
+            Error::RefNotFound { ref_name } => write!(f, "Ref '{}' not found", ref_name),
+            Error::CommitNotInHistory { oid, head_only: true } => {
+                write!(f, "Commit {} not found in current branch history", oid)
+            }
+            Error::CommitNotInHistory { oid, head_only: false } => {
+                write!(f, "Commit {} not found in the history of the selected refs", oid)
+            }
+            Error::InvalidOid { value, .. } => write!(f, "'{}' is not a valid commit OID", value),
+            Error::InvalidSignature { field, git } => {
+                write!(f, "Invalid {} name or email: {}", field, git.message)
+            }
+            Error::InvalidInput { message, .. } => write!(f, "{}", message),
+            Error::DuplicateEdit { oid } => write!(f, "Commit {} is listed more than once", oid),
+            Error::NothingToRewrite { message } => write!(f, "{}", message),
+            Error::SigningFailed { oid, message } => write!(f, "Failed to sign commit {}: {}", oid, message),
+            Error::MergeConflict { oid, paths } => {
+                write!(f, "Commit {} conflicts in {}", oid, paths.join(", "))
+            }
+            Error::AlreadyPublished { oid, refs } => write!(
+                f,
+                "Commit {} is already in {}; rewriting it needs a force push",
+                oid,
+                refs.join(", ")
+            ),
+            Error::RefMoved { ref_name, recovery_refs } if recovery_refs.is_empty() => {
+                write!(f, "{} was moved by another process; nothing was changed", ref_name)
+            }
+            Error::RefMoved { ref_name, recovery_refs } => write!(
+                f,
+                "{} was moved by another process during the rewrite, so it was left alone. \
+                 The rewritten history is kept at {}",
+                ref_name,
+                recovery_refs.join(", ")
+            ),
+            Error::DirtyWorktree { paths, unapplied_stash: None } => write!(
+                f,
+                "Local changes to {} would be overwritten by the rewritten HEAD; commit or stash them first",
+                paths.join(", ")
+            ),
+            Error::DirtyWorktree { paths, unapplied_stash: Some(stash) } => write!(
+                f,
+                "Files in the way of the rewritten HEAD: {}. Your auto-stashed changes could not be \
+                 re-applied and are kept in stash {}",
+                paths.join(", "),
+                stash
+            ),
+            Error::NonMonotonicDate { oid, parent_oid } => write!(
+                f,
+                "Commit {} would get an earlier committer date than its parent {}",
+                oid, parent_oid
+            ),
+            Error::PermissionDenied { path: Some(path), git } => {
+                write!(f, "Permission denied writing {}: {}", path, git.message)
+            }
+            Error::PermissionDenied { path: None, git } => write!(f, "Permission denied: {}", git.message),
+            Error::RefUpdateFailed { ref_name, git } => {
+                write!(f, "Failed to update ref {}: {}", ref_name, git.message)
+            }
+            Error::BackupNotFound { entry_id: Some(id) } => write!(f, "Backup '{}' not found.", id),
+            Error::BackupNotFound { entry_id: None } => write!(f, "No backup found for this branch."),
+            Error::NothingToRedo => write!(f, "Nothing to redo for this branch."),
+            Error::Io { path, message } => write!(f, "Failed to read {}: {}", path, message),
+            Error::Git(git) => write!(f, "{}", git.message),
+            Error::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Permission problems are told apart where the path is known, see `access_denied`.
+impl From<git2::Error> for Error {
+    fn from(e: git2::Error) -> Self {
+        Error::Git(GitErrorInfo::from(&e))
+    }
+}
+
+impl Serialize for Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Error", 10)?;
+        s.serialize_field("kind", self.kind())?;
+        s.serialize_field("message", &self.to_string())?;
+        if let Some(field) = self.field() {
+            s.serialize_field("field", field)?;
+        }
+        if let Some(oid) = self.oid() {
+            s.serialize_field("oid", oid)?;
+        }
+        if let Some(parent_oid) = self.parent_oid() {
+            s.serialize_field("parent_oid", parent_oid)?;
+        }
+        if let Some(ref_name) = self.ref_name() {
+            s.serialize_field("ref_name", ref_name)?;
+        }
+        if let Some(paths) = self.paths() {
+            s.serialize_field("paths", paths)?;
+        }
+        if let Some(refs) = self.refs() {
+            s.serialize_field("refs", refs)?;
+        }
+        if let Some(stash) = self.unapplied_stash() {
+            s.serialize_field("unapplied_stash", stash)?;
+        }
+        if let Some(git) = self.git() {
+            s.serialize_field("git", git)?;
+        }
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_kind_and_details() {
+        let err = crate::repo::parse_oid("not-an-oid").unwrap_err();
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["kind"], "invalid_oid");
+        assert_eq!(json["oid"], "not-an-oid");
+        assert_eq!(json["message"], "'not-an-oid' is not a valid commit OID");
+        assert!(json["git"]["class"].is_string());
+        assert!(json["git"]["code"].is_string());
+        assert!(json.get("field").is_none());
+
+        let json = serde_json::to_value(Error::DetachedHead {
+            action: "undo".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json["kind"], "detached_head");
+        assert!(json.get("git").is_none());
+
+        let err = Error::NonMonotonicDate {
+            oid: "abc".to_string(),
+            parent_oid: "def".to_string(),
+        };
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["oid"], "abc");
+        assert_eq!(json["parent_oid"], "def");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permission_denied_is_classified_by_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, repo) = crate::test_support::create_test_repo(1);
+        let heads = repo.path().join("refs/heads");
+        std::fs::set_permissions(&heads, std::fs::Permissions::from_mode(0o555)).unwrap();
+        let os_error = || {
+            git2::Error::new(
+                git2::ErrorCode::GenericError,
+                git2::ErrorClass::Os,
+                "failed to create locked file",
+            )
+        };
+
+        let err = Error::ref_update(&repo, "refs/heads/main", os_error());
+        assert_eq!(err.kind(), "permission_denied");
+        assert_eq!(
+            err.to_string().split(':').next(),
+            Some("Permission denied writing refs/heads/main")
+        );
+        // Reading a read-only directory is fine
+        assert!(!access_denied(&os_error(), &heads, false));
+
+        // Only OS errors are checked against the filesystem
+        let err = Error::ref_update(
+            &repo,
+            "refs/heads/main",
+            git2::Error::new(
+                git2::ErrorCode::Locked,
+                git2::ErrorClass::Reference,
+                "permission denied",
+            ),
+        );
+        assert_eq!(err.kind(), "ref_update_failed");
+        let err = Error::ref_update(&repo, "refs/tags/v1", os_error());
+        assert_eq!(err.kind(), "ref_update_failed");
+        std::fs::set_permissions(&heads, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use crate::error::Error;
+use git2::{Oid, Repository, RevparseMode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct CommitFilters {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub message_pattern: Option<String>,
+    pub date_start: Option<i64>,
+    pub date_end: Option<i64>,
+    pub file_path: Option<String>,
+}
+
+/// The commits a bulk rewrite applies to. A commit has to match both the
+/// range and the filters; an empty selection matches every commit.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CommitSelection {
+    /// A revision range such as `main..HEAD`, or a single revision for
+    /// everything reachable from it.
+    pub range: Option<String>,
+    pub filters: Option<CommitFilters>,
+}
+
+/// A `CommitSelection` with its range resolved to a set of commits.
+pub(crate) struct SelectionMatcher<'s> {
+    range: Option<HashSet<Oid>>,
+    filters: Option<&'s CommitFilters>,
+}
+
+impl CommitSelection {
+    pub(crate) fn matcher(&self, repo: &Repository) -> Result<SelectionMatcher<'_>, Error> {
+        let range = match &self.range {
+            Some(range) => Some(commits_in_range(repo, range)?),
+            None => None,
+        };
+        Ok(SelectionMatcher {
+            range,
+            filters: self.filters.as_ref(),
+        })
+    }
+}
+
+impl SelectionMatcher<'_> {
+    pub(crate) fn matches(&self, repo: &Repository, commit: &git2::Commit) -> Result<bool, Error> {
+        if let Some(range) = &self.range {
+            if !range.contains(&commit.id()) {
+                return Ok(false);
+            }
+        }
+        match self.filters {
+            Some(filters) => matches_filters(repo, commit, filters),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Resolve `A..B` (reachable from B but not from A) or a single revision
+/// (everything reachable from it), like `git log` does.
+fn commits_in_range(repo: &Repository, range: &str) -> Result<HashSet<Oid>, Error> {
+    let spec = repo.revparse(range).map_err(|e| {
+        Error::invalid_input(
+            "range",
+            format!("Invalid revision range '{}': {}", range, e.message()),
+        )
+    })?;
+    if spec.mode().contains(RevparseMode::MERGE_BASE) {
+        return Err(Error::invalid_input(
+            "range",
+            "Symmetric ranges (A...B) are not supported",
+        ));
+    }
+
+    let mut revwalk = repo.revwalk()?;
+    let (from, to) = (spec.from(), spec.to());
+    match (spec.mode().contains(RevparseMode::RANGE), from, to) {
+        (true, Some(from), Some(to)) => {
+            revwalk.push(to.peel_to_commit()?.id())?;
+            revwalk.hide(from.peel_to_commit()?.id())?;
+        }
+        (false, Some(single), _) => revwalk.push(single.peel_to_commit()?.id())?,
+        _ => {
+            return Err(Error::invalid_input(
+                "range",
+                format!("Invalid revision range '{}'", range),
+            ))
+        }
+    }
+    Ok(revwalk.collect::<Result<_, _>>()?)
+}
+
+pub(crate) fn matches_filters(
+    repo: &Repository,
+    commit: &git2::Commit,
+    filters: &CommitFilters,
+) -> Result<bool, Error> {
+    let author = commit.author();
+    let author_name = author.name().unwrap_or("");
+    let author_email = author.email().unwrap_or("");
+    let author_date = author.when().seconds();
+    let message = commit.message().unwrap_or("");
+
+    // Author name filter (case-insensitive substring match)
+    if let Some(ref name_filter) = filters.author_name {
+        if !author_name
+            .to_lowercase()
+            .contains(&name_filter.to_lowercase())
+        {
+            return Ok(false);
+        }
+    }
+
+    // Author email filter (case-insensitive substring match)
+    if let Some(ref email_filter) = filters.author_email {
+        if !author_email
+            .to_lowercase()
+            .contains(&email_filter.to_lowercase())
+        {
+            return Ok(false);
+        }
+    }
+
+    // Message pattern filter (case-insensitive substring match)
+    if let Some(ref msg_filter) = filters.message_pattern {
+        if !message.to_lowercase().contains(&msg_filter.to_lowercase()) {
+            return Ok(false);
+        }
+    }
+
+    // Date range filter
+    if let Some(start) = filters.date_start {
+        if author_date < start {
+            return Ok(false);
+        }
+    }
+    if let Some(end) = filters.date_end {
+        if author_date > end {
+            return Ok(false);
+        }
+    }
+
+    // File path filter - check if commit touches the specified path
+    if let Some(ref file_path) = filters.file_path {
+        let touches_file = commit_touches_path(repo, commit, file_path)?;
+        if !touches_file {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn commit_touches_path(
+    repo: &Repository,
+    commit: &git2::Commit,
+    path_pattern: &str,
+) -> Result<bool, Error> {
+    // Get commit tree
+    let commit_tree = commit.tree()?;
+
+    // For root commits (no parents), check if path exists in tree
+    if commit.parent_count() == 0 {
+        return Ok(tree_contains_path(&commit_tree, path_pattern));
+    }
+
+    // For commits with parents, check diff against each parent
+    for parent_id in commit.parent_ids() {
+        let parent = repo.find_commit(parent_id)?;
+        let parent_tree = parent.tree()?;
+
+        let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit_tree), None)?;
+
+        // Check if any delta touches our path
+        for delta in diff.deltas() {
+            let old_path = delta.old_file().path();
+            let new_path = delta.new_file().path();
+
+            if let Some(p) = old_path {
+                if path_matches_pattern(p, path_pattern) {
+                    return Ok(true);
+                }
+            }
+            if let Some(p) = new_path {
+                if path_matches_pattern(p, path_pattern) {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+fn tree_contains_path(tree: &git2::Tree, path_pattern: &str) -> bool {
+    tree.iter().any(|entry| {
+        if let Some(name) = entry.name() {
+            path_matches_pattern(std::path::Path::new(name), path_pattern)
+        } else {
+            false
+        }
+    })
+}
+
+fn path_matches_pattern(path: &std::path::Path, pattern: &str) -> bool {
+    let path_str = path.to_string_lossy();
+
+    // Support simple glob patterns
+    if pattern.contains('*') {
+        // Convert simple glob to regex-like matching
+        // For now, support * as "any characters"
+        let pattern_lower = pattern.to_lowercase();
+        let path_lower = path_str.to_lowercase();
+
+        if let Some(suffix) = pattern_lower.strip_prefix("*/") {
+            // Matches any directory prefix
+            return path_lower.ends_with(suffix) || path_lower.contains(&format!("/{}", suffix));
+        } else if pattern_lower.ends_with("/*") {
+            // Matches any file in directory
+            let prefix = &pattern_lower[..pattern_lower.len() - 2];
+            return path_lower.starts_with(prefix);
+        } else if pattern_lower.contains("*/") {
+            // Contains wildcard in middle - do substring match on parts
+            let parts: Vec<&str> = pattern_lower.split("*/").collect();
+            return parts.iter().all(|part| path_lower.contains(part));
+        } else {
+            // Simple wildcard - just check if pattern parts are in path
+            let pattern_parts: Vec<&str> =
+                pattern_lower.split('*').filter(|s| !s.is_empty()).collect();
+            return pattern_parts.iter().all(|part| path_lower.contains(part));
+        }
+    }
+
+    // Exact match (case-insensitive)
+    path_str.to_lowercase() == pattern.to_lowercase()
+        || path_str
+            .to_lowercase()
+            .ends_with(&format!("/{}", pattern.to_lowercase()))
+}
@@ -0,0 +1,449 @@
+use crate::error::Error;
+use crate::rewrite::{
+    execute_rewrite, plan_rewrite, CommitEdit, RefSelection, RewriteOptions, RewriteResult,
+};
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// What an identity rule matches. Names and emails compare
+/// case-insensitively, as in `.mailmap`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityMatch {
+    Email(String),
+    Name(String),
+    /// Both must match; this is the long `.mailmap` form.
+    NameAndEmail {
+        name: String,
+        email: String,
+    },
+    /// Regular expression matched against `Name <email>`.
+    Pattern(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityRole {
+    Author,
+    Committer,
+    #[default]
+    Both,
+}
+
+/// Replace the name and/or email of every identity matched by `matches`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct IdentityRule {
+    pub matches: IdentityMatch,
+    pub new_name: Option<String>,
+    pub new_email: Option<String>,
+    #[serde(default)]
+    pub applies_to: IdentityRole,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IdentityRuleHits {
+    pub rule_index: usize,
+    pub commits_matched: usize,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct IdentityRewritePreview {
+    pub rules: Vec<IdentityRuleHits>,
+    /// Commits whose author or committer would change.
+    pub commits_affected: usize,
+    /// Affected commits plus all of their descendants.
+    pub commits_to_rewrite: usize,
+}
+
+struct CompiledIdentityRule<'a> {
+    rule: &'a IdentityRule,
+    pattern: Option<regex::Regex>,
+}
+
+impl CompiledIdentityRule<'_> {
+    fn matches(&self, name: &str, email: &str) -> bool {
+        match &self.rule.matches {
+            IdentityMatch::Email(e) => e.eq_ignore_ascii_case(email),
+            IdentityMatch::Name(n) => n.eq_ignore_ascii_case(name),
+            IdentityMatch::NameAndEmail { name: n, email: e } => {
+                n.eq_ignore_ascii_case(name) && e.eq_ignore_ascii_case(email)
+            }
+            IdentityMatch::Pattern(_) => self
+                .pattern
+                .as_ref()
+                .is_some_and(|re| re.is_match(&format!("{} <{}>", name, email))),
+        }
+    }
+}
+
+fn compile_identity_rules(rules: &[IdentityRule]) -> Result<Vec<CompiledIdentityRule<'_>>, Error> {
+    if rules.is_empty() {
+        return Err(Error::invalid_input("rules", "No identity rules given"));
+    }
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            if rule.new_name.is_none() && rule.new_email.is_none() {
+                return Err(Error::invalid_input(
+                    format!("rules[{}]", i),
+                    format!("Identity rule {} changes neither name nor email", i + 1),
+                ));
+            }
+            let pattern = match &rule.matches {
+                IdentityMatch::Pattern(p) => Some(regex::Regex::new(p).map_err(|e| {
+                    Error::invalid_input(
+                        format!("rules[{}].matches", i),
+                        format!("Invalid pattern in identity rule {}: {}", i + 1, e),
+                    )
+                })?),
+                _ => None,
+            };
+            Ok(CompiledIdentityRule { rule, pattern })
+        })
+        .collect()
+}
+
+/// Work out the edit the identity rules make to one commit, and the indices
+/// of the rules that matched it. The first matching rule wins for each role.
+fn identity_edit(
+    commit: &git2::Commit,
+    rules: &[CompiledIdentityRule],
+) -> (Option<CommitEdit>, Vec<usize>) {
+    let mut edit = CommitEdit::default();
+    let mut changed = false;
+    let mut matched_rules: Vec<usize> = Vec::new();
+
+    for role in [IdentityRole::Author, IdentityRole::Committer] {
+        let sig = match role {
+            IdentityRole::Author => commit.author(),
+            _ => commit.committer(),
+        };
+        let name = sig.name().unwrap_or("");
+        let email = sig.email().unwrap_or("");
+
+        let Some((idx, rule)) = rules.iter().enumerate().find(|(_, r)| {
+            (r.rule.applies_to == role || r.rule.applies_to == IdentityRole::Both)
+                && r.matches(name, email)
+        }) else {
+            continue;
+        };
+        if !matched_rules.contains(&idx) {
+            matched_rules.push(idx);
+        }
+
+        let new_name = rule.rule.new_name.clone().filter(|n| n != name);
+        let new_email = rule.rule.new_email.clone().filter(|e| e != email);
+        changed |= new_name.is_some() || new_email.is_some();
+        match role {
+            IdentityRole::Author => {
+                edit.author_name = new_name;
+                edit.author_email = new_email;
+            }
+            _ => {
+                edit.committer_name = new_name;
+                edit.committer_email = new_email;
+            }
+        }
+    }
+
+    (changed.then_some(edit), matched_rules)
+}
+
+/// Dry run of `apply_identity_rules`: counts how many commits each rule hits
+/// without writing any objects or refs.
+pub fn preview_identity_rules(
+    repo: &Repository,
+    rules: &[IdentityRule],
+    refs: &RefSelection,
+) -> Result<IdentityRewritePreview, Error> {
+    let compiled = compile_identity_rules(rules)?;
+    let plan = plan_rewrite(repo, refs)?;
+
+    let mut hits = vec![0; rules.len()];
+    let mut dirty: HashSet<Oid> = HashSet::new();
+    let mut commits_affected = 0;
+
+    for oid in &plan.oids {
+        let commit = repo.find_commit(*oid)?;
+        let (edit, matched_rules) = identity_edit(&commit, &compiled);
+        for idx in matched_rules {
+            hits[idx] += 1;
+        }
+        let affected = edit.is_some();
+        if affected {
+            commits_affected += 1;
+        }
+        if affected || commit.parent_ids().any(|pid| dirty.contains(&pid)) {
+            dirty.insert(*oid);
+        }
+    }
+
+    Ok(IdentityRewritePreview {
+        rules: hits
+            .into_iter()
+            .enumerate()
+            .map(|(rule_index, commits_matched)| IdentityRuleHits {
+                rule_index,
+                commits_matched,
+            })
+            .collect(),
+        commits_affected,
+        commits_to_rewrite: dirty.len(),
+    })
+}
+
+/// Rewrite author and committer identities across the history of
+/// `options.refs` according to `rules`, in a single walk.
+pub fn apply_identity_rules(
+    repo: &Repository,
+    rules: &[IdentityRule],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let compiled = compile_identity_rules(rules)?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| Ok(identity_edit(commit, &compiled).0.into()),
+        "rewrote identities",
+        options,
+        on_progress,
+    )?;
+
+    if outcome.edited.is_empty() {
+        return Err(Error::nothing_to_rewrite(
+            "No commits match the identity rules",
+        ));
+    }
+    outcome.into_result(None, options.dry_run)
+}
+
+/// Read a `.mailmap` file and parse it with `parse_mailmap`.
+pub fn load_mailmap_file(path: &Path) -> Result<Vec<IdentityRule>, Error> {
+    let contents = std::fs::read_to_string(path).map_err(|e| Error::Io {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    Ok(parse_mailmap(&contents))
+}
+
+/// Parse `.mailmap` contents into identity rules. Entries that match on both
+/// name and email come first so they take precedence over email-only entries,
+/// mirroring git's lookup order.
+pub fn parse_mailmap(contents: &str) -> Vec<IdentityRule> {
+    fn take_email(s: &str) -> Option<(&str, &str, &str)> {
+        let open = s.find('<')?;
+        let close = open + s[open..].find('>')?;
+        Some((s[..open].trim(), &s[open + 1..close], &s[close + 1..]))
+    }
+
+    let mut rules = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((proper_name, first_email, rest)) = take_email(line) else {
+            continue;
+        };
+        let proper_name = (!proper_name.is_empty()).then(|| proper_name.to_string());
+
+        let rule = match take_email(rest) {
+            // Proper Name <commit@email>
+            None => IdentityRule {
+                matches: IdentityMatch::Email(first_email.to_string()),
+                new_name: proper_name,
+                new_email: None,
+                applies_to: IdentityRole::Both,
+            },
+            // [Proper Name] <proper@email> [Commit Name] <commit@email>
+            Some((commit_name, commit_email, _)) => IdentityRule {
+                matches: if commit_name.is_empty() {
+                    IdentityMatch::Email(commit_email.to_string())
+                } else {
+                    IdentityMatch::NameAndEmail {
+                        name: commit_name.to_string(),
+                        email: commit_email.to_string(),
+                    }
+                },
+                new_name: proper_name,
+                new_email: Some(first_email.to_string()),
+                applies_to: IdentityRole::Both,
+            },
+        };
+        if rule.new_name.is_some() || rule.new_email.is_some() {
+            rules.push(rule);
+        }
+    }
+
+    rules.sort_by_key(|r| !matches!(r.matches, IdentityMatch::NameAndEmail { .. }));
+    rules
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_detail, list_commits};
+    use crate::test_support::create_test_repo;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    fn identity_test_repo() -> (TempDir, Repository) {
+        let (dir, repo) = create_test_repo(3);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let old = Signature::now("Old Name", "Old@Corp.com").unwrap();
+        {
+            let parent = repo.find_commit(head_oid).unwrap();
+            let tree = parent.tree().unwrap();
+            repo.commit(
+                Some("HEAD"),
+                &old,
+                &old,
+                "By old identity",
+                &tree,
+                &[&parent],
+            )
+            .unwrap();
+        }
+        (dir, repo)
+    }
+
+    #[test]
+    fn test_identity_rules_preview_and_apply() {
+        let (_dir, repo) = identity_test_repo();
+        let head_before = repo.head().unwrap().target().unwrap();
+
+        let rules = vec![
+            IdentityRule {
+                matches: IdentityMatch::Email("old@corp.com".to_string()),
+                new_name: Some("New Name".to_string()),
+                new_email: Some("new@corp.com".to_string()),
+                applies_to: IdentityRole::Both,
+            },
+            IdentityRule {
+                matches: IdentityMatch::Pattern(r"^Test User <.*@example\.com>$".to_string()),
+                new_name: None,
+                new_email: Some("test@example.org".to_string()),
+                applies_to: IdentityRole::Author,
+            },
+        ];
+
+        let preview = preview_identity_rules(&repo, &rules, &RefSelection::Head).unwrap();
+        assert_eq!(preview.rules[0].commits_matched, 1);
+        assert_eq!(preview.rules[1].commits_matched, 3);
+        assert_eq!(preview.commits_affected, 4);
+        assert_eq!(preview.commits_to_rewrite, 4);
+        // The preview must not touch anything
+        assert_eq!(repo.head().unwrap().target().unwrap(), head_before);
+
+        let result =
+            apply_identity_rules(&repo, &rules, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.edited_commits.len(), 4);
+
+        let detail = commit_detail(
+            &repo,
+            Oid::from_str(&result.updated_refs[0].new_oid).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(detail.author_name, "New Name");
+        assert_eq!(detail.author_email, "new@corp.com");
+        assert_eq!(detail.committer_email, "new@corp.com");
+
+        // Author-only rule leaves the committer alone
+        let root = list_commits(&repo, 0, 10).unwrap().pop().unwrap();
+        let root_detail = commit_detail(&repo, Oid::from_str(&root.oid).unwrap()).unwrap();
+        assert_eq!(root_detail.author_email, "test@example.org");
+        assert_eq!(root_detail.committer_email, "test@example.com");
+    }
+
+    #[test]
+    fn test_identity_rules_without_matches_fail() {
+        let (_dir, repo) = create_test_repo(2);
+        let rules = vec![IdentityRule {
+            matches: IdentityMatch::Name("Nobody".to_string()),
+            new_name: Some("Somebody".to_string()),
+            new_email: None,
+            applies_to: IdentityRole::Both,
+        }];
+        let err = apply_identity_rules(&repo, &rules, &RewriteOptions::default(), &|_, _| {}).err();
+        assert_eq!(err.map(|e| e.kind()), Some("nothing_to_rewrite"));
+
+        let bad_pattern = vec![IdentityRule {
+            matches: IdentityMatch::Pattern("(".to_string()),
+            new_name: Some("Somebody".to_string()),
+            new_email: None,
+            applies_to: IdentityRole::Both,
+        }];
+        let err = preview_identity_rules(&repo, &bad_pattern, &RefSelection::Head)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "invalid_input");
+        assert_eq!(err.field(), Some("rules[0].matches"));
+    }
+
+    #[test]
+    fn test_mailmap_matches_names_and_emails_case_insensitively() {
+        let (_dir, repo) = identity_test_repo();
+        let rules = parse_mailmap(
+            "New Name <new@corp.com> old name <OLD@corp.COM>\n\
+             Tester <TEST@EXAMPLE.COM>\n",
+        );
+        let preview = preview_identity_rules(&repo, &rules, &RefSelection::Head).unwrap();
+        assert_eq!(preview.rules[0].commits_matched, 1);
+        assert_eq!(preview.rules[1].commits_matched, 3);
+
+        let by_name = vec![IdentityRule {
+            matches: IdentityMatch::Name("TEST USER".to_string()),
+            new_name: Some("Tester".to_string()),
+            new_email: None,
+            applies_to: IdentityRole::Both,
+        }];
+        let preview = preview_identity_rules(&repo, &by_name, &RefSelection::Head).unwrap();
+        assert_eq!(preview.rules[0].commits_matched, 3);
+
+        let result =
+            apply_identity_rules(&repo, &rules, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        let detail = commit_detail(
+            &repo,
+            Oid::from_str(&result.updated_refs[0].new_oid).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(detail.author_name, "New Name");
+        assert_eq!(detail.author_email, "new@corp.com");
+        let root = list_commits(&repo, 0, 10).unwrap().pop().unwrap();
+        let root_detail = commit_detail(&repo, Oid::from_str(&root.oid).unwrap()).unwrap();
+        assert_eq!(root_detail.author_name, "Tester");
+    }
+
+    #[test]
+    fn test_parse_mailmap() {
+        let rules = parse_mailmap(
+            "# comment\n\
+             Proper Name <commit@email.xx>\n\
+             <proper@email.xx> <commit2@email.xx>\n\
+             Other Name <proper@email.xx> Commit Name <commit3@email.xx>\n",
+        );
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0].matches,
+            IdentityMatch::NameAndEmail {
+                name: "Commit Name".to_string(),
+                email: "commit3@email.xx".to_string(),
+            }
+        );
+        assert_eq!(rules[0].new_name.as_deref(), Some("Other Name"));
+        assert_eq!(
+            rules[1].matches,
+            IdentityMatch::Email("commit@email.xx".to_string())
+        );
+        assert_eq!(rules[1].new_name.as_deref(), Some("Proper Name"));
+        assert_eq!(rules[1].new_email, None);
+        assert_eq!(rules[2].new_name, None);
+        assert_eq!(rules[2].new_email.as_deref(), Some("proper@email.xx"));
+    }
+}
@@ -0,0 +1,70 @@
+//! Repository reading, filtering, history rewriting and backup handling for
+//! git-history-editor, with no dependency on Tauri. The desktop app's IPC
+//! commands and the `ghe` command-line tool are thin wrappers around this crate.
+
+pub mod backup;
+pub mod dates;
+pub mod diff;
+pub mod drop;
+pub mod error;
+pub mod filter;
+pub mod identity;
+pub mod messages;
+pub mod purge;
+pub mod reorder;
+pub mod repo;
+pub mod rewrite;
+pub mod safety;
+pub mod scrub;
+pub mod signing;
+pub mod split;
+pub mod squash;
+pub mod tags;
+pub mod trailers;
+mod worktree;
+
+#[cfg(test)]
+mod test_support;
+
+pub use backup::{
+    backup_status, delete_backup_entry, list_backup_entries, prune_backups, redo_rewrite,
+    restore_backup_entry, undo_rewrite, BackupEntry, BackupInfo, BackupKind, BackupPrune,
+    BackupRef,
+};
+pub use dates::{rewrite_dates, DateRewrite};
+pub use diff::{
+    changed_files, file_patch, FileChange, FilePatch, FileStatus, ParentDiff,
+    DEFAULT_PATCH_SIZE_LIMIT,
+};
+pub use drop::drop_commits;
+pub use error::{Error, GitErrorInfo};
+pub use filter::{CommitFilters, CommitSelection};
+pub use git2;
+pub use identity::{
+    apply_identity_rules, load_mailmap_file, parse_mailmap, preview_identity_rules, IdentityMatch,
+    IdentityRewritePreview, IdentityRole, IdentityRule, IdentityRuleHits,
+};
+pub use messages::{
+    preview_message_rules, rewrite_messages, MessageChange, MessageRewritePreview, MessageRule,
+};
+pub use purge::{remove_paths, RemovePathsOptions, RemovePathsResult};
+pub use reorder::reorder_commits;
+pub use repo::{
+    commit_detail, list_commits, list_commits_filtered, open_repo, parse_oid, repository_info,
+    CommitDetail, CommitSummary, RepoInfo,
+};
+pub use rewrite::{
+    rewrite_commit, rewrite_commits, CommitChange, CommitEdit, OidMapping, RefSelection, RefUpdate,
+    RewriteOptions, RewriteResult,
+};
+pub use safety::{published_refs, PublishedRefs};
+pub use scrub::{
+    scrub_blobs, ScrubOptions, ScrubResult, ScrubbedCommit, DEFAULT_SCRUB_REPLACEMENT,
+};
+pub use signing::{CommitSigner, GpgSigner, SigningMode, SshSigner};
+pub use split::{split_commit, SplitGroup};
+pub use squash::{
+    render_squash_message, squash_commits, SquashMode, SquashOptions, DEFAULT_SQUASH_TEMPLATE,
+};
+pub use tags::TagRewrite;
+pub use trailers::{apply_trailer_edits, parse_trailers, rewrite_trailers, Trailer, TrailerEdit};
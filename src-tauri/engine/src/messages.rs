@@ -0,0 +1,294 @@
+use crate::error::Error;
+use crate::filter::{CommitSelection, SelectionMatcher};
+use crate::rewrite::{
+    execute_rewrite, plan_rewrite, CommitAction, CommitEdit, RefSelection, RewriteOptions,
+    RewriteResult,
+};
+use git2::{Oid, Repository};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Replace every match of `pattern` in a commit message with `replacement`.
+/// The replacement may refer to capture groups as `$1` or `${name}`; write
+/// `$$` for a literal dollar sign.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MessageRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// One commit whose message the rules would change.
+#[derive(Serialize, Clone, Debug)]
+pub struct MessageChange {
+    pub oid: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct MessageRewritePreview {
+    /// Changed messages, oldest commit first.
+    pub changes: Vec<MessageChange>,
+    /// Changed commits plus all of their descendants.
+    pub commits_to_rewrite: usize,
+}
+
+fn compile_message_rules(rules: &[MessageRule]) -> Result<Vec<(Regex, &str)>, Error> {
+    if rules.is_empty() {
+        return Err(Error::invalid_input("rules", "No message rules given"));
+    }
+    rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            let pattern = Regex::new(&rule.pattern).map_err(|e| {
+                Error::invalid_input(
+                    format!("rules[{}].pattern", i),
+                    format!("Invalid pattern in message rule {}: {}", i + 1, e),
+                )
+            })?;
+            Ok((pattern, rule.replacement.as_str()))
+        })
+        .collect()
+}
+
+/// Apply the rules in order, each to the output of the previous one. Returns
+/// `None` when the message comes out unchanged.
+fn rewritten_message(message: &str, rules: &[(Regex, &str)]) -> Option<String> {
+    let mut current = message.to_string();
+    for (pattern, replacement) in rules {
+        if let std::borrow::Cow::Owned(replaced) = pattern.replace_all(&current, *replacement) {
+            current = replaced;
+        }
+    }
+    (current != message).then_some(current)
+}
+
+/// The new message of `commit`, if it is selected and the rules change it.
+/// Messages that are not UTF-8 are left alone rather than mangled.
+fn message_edit(
+    repo: &Repository,
+    commit: &git2::Commit,
+    matcher: &SelectionMatcher,
+    rules: &[(Regex, &str)],
+) -> Result<Option<String>, Error> {
+    let Some(message) = commit.message() else {
+        return Ok(None);
+    };
+    if !matcher.matches(repo, commit)? {
+        return Ok(None);
+    }
+    Ok(rewritten_message(message, rules))
+}
+
+/// Dry run of `rewrite_messages`: the before and after message of every
+/// commit the rules would change, without writing any objects or refs.
+pub fn preview_message_rules(
+    repo: &Repository,
+    selection: &CommitSelection,
+    rules: &[MessageRule],
+    refs: &RefSelection,
+) -> Result<MessageRewritePreview, Error> {
+    let compiled = compile_message_rules(rules)?;
+    let matcher = selection.matcher(repo)?;
+    let plan = plan_rewrite(repo, refs)?;
+
+    let mut changes = Vec::new();
+    let mut dirty: HashSet<Oid> = HashSet::new();
+    for oid in &plan.oids {
+        let commit = repo.find_commit(*oid)?;
+        let edited = message_edit(repo, &commit, &matcher, &compiled)?;
+        let affected = edited.is_some();
+        if let Some(after) = edited {
+            changes.push(MessageChange {
+                oid: oid.to_string(),
+                before: commit.message().unwrap_or("").to_string(),
+                after,
+            });
+        }
+        if affected || commit.parent_ids().any(|pid| dirty.contains(&pid)) {
+            dirty.insert(*oid);
+        }
+    }
+
+    Ok(MessageRewritePreview {
+        changes,
+        commits_to_rewrite: dirty.len(),
+    })
+}
+
+/// Rewrite the message of every commit matching `selection` with regex
+/// substitution `rules`, like `git filter-repo --replace-message`, in a single
+/// walk over the history of `options.refs`.
+pub fn rewrite_messages(
+    repo: &Repository,
+    selection: &CommitSelection,
+    rules: &[MessageRule],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let compiled = compile_message_rules(rules)?;
+    let matcher = selection.matcher(repo)?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            Ok(match message_edit(repo, commit, &matcher, &compiled)? {
+                Some(message) => CommitAction::Edit {
+                    edit: CommitEdit {
+                        message: Some(message),
+                        ..Default::default()
+                    },
+                    tree: None,
+                },
+                None => CommitAction::Keep,
+            })
+        },
+        "rewrote messages",
+        options,
+        on_progress,
+    )?;
+
+    if outcome.edited.is_empty() {
+        return Err(Error::nothing_to_rewrite(
+            "No selected commit message matches the rules",
+        ));
+    }
+    outcome.into_result(None, options.dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::CommitFilters;
+    use crate::repo::list_commits;
+    use crate::test_support::{commit_files, create_test_repo};
+
+    fn rule(pattern: &str, replacement: &str) -> MessageRule {
+        MessageRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    fn messages(repo: &Repository) -> Vec<String> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.short_message)
+            .collect()
+    }
+
+    fn ticket_repo() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = create_test_repo(1);
+        commit_files(&repo, &[("a.txt", Some(b"a"))], "JIRA-12: Add a");
+        commit_files(&repo, &[("b.txt", Some(b"b"))], "Fix teh build");
+        commit_files(
+            &repo,
+            &[("c.txt", Some(b"c"))],
+            "JIRA-7: Add c, see JIRA-12",
+        );
+        (dir, repo)
+    }
+
+    #[test]
+    fn test_rewrite_messages_with_capture_groups() {
+        let (_dir, repo) = ticket_repo();
+        let rules = [rule(r"JIRA-(\d+)", "PROJ-$1"), rule(r"\bteh\b", "the")];
+
+        let preview = preview_message_rules(
+            &repo,
+            &CommitSelection::default(),
+            &rules,
+            &RefSelection::Head,
+        )
+        .unwrap();
+        let afters: Vec<&str> = preview.changes.iter().map(|c| c.after.as_str()).collect();
+        assert_eq!(
+            afters,
+            [
+                "PROJ-12: Add a",
+                "Fix the build",
+                "PROJ-7: Add c, see PROJ-12"
+            ]
+        );
+        assert_eq!(preview.changes[1].before, "Fix teh build");
+        assert_eq!(preview.commits_to_rewrite, 3);
+
+        rewrite_messages(
+            &repo,
+            &CommitSelection::default(),
+            &rules,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            messages(&repo),
+            [
+                "PROJ-7: Add c, see PROJ-12",
+                "Fix the build",
+                "PROJ-12: Add a",
+                "Commit 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_messages_respects_selection() {
+        let (_dir, repo) = ticket_repo();
+        let selection = CommitSelection {
+            range: None,
+            filters: Some(CommitFilters {
+                message_pattern: Some("add c".to_string()),
+                ..Default::default()
+            }),
+        };
+        let rules = [rule(r"JIRA-(\d+)", "PROJ-$1")];
+
+        let preview =
+            preview_message_rules(&repo, &selection, &rules, &RefSelection::Head).unwrap();
+        assert_eq!(preview.changes.len(), 1);
+        assert_eq!(preview.commits_to_rewrite, 1);
+
+        rewrite_messages(
+            &repo,
+            &selection,
+            &rules,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            messages(&repo)[..3],
+            [
+                "PROJ-7: Add c, see PROJ-12",
+                "Fix teh build",
+                "JIRA-12: Add a"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rewrite_messages_validation() {
+        let (_dir, repo) = ticket_repo();
+        let all = CommitSelection::default();
+        let options = RewriteOptions::default();
+
+        let err = rewrite_messages(&repo, &all, &[], &options, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "invalid_input");
+        let err = rewrite_messages(&repo, &all, &[rule("(", "x")], &options, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.field(), Some("rules[0].pattern"));
+        let err = rewrite_messages(&repo, &all, &[rule("nowhere", "x")], &options, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "nothing_to_rewrite");
+    }
+}
@@ -0,0 +1,330 @@
+use crate::error::Error;
+use crate::rewrite::{execute_rewrite, plan_rewrite, CommitAction, RewriteOptions, RewriteResult};
+use git2::{
+    ObjectType, Oid, Pathspec, PathspecFlags, Repository, Tree, TreeWalkMode, TreeWalkResult,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct RemovePathsOptions {
+    /// Git pathspecs to remove, such as `.env` (top level only), `data/` or
+    /// `*.csv` (any depth, since `*` also matches `/`). A matching directory
+    /// is removed with everything below it.
+    pub paths: Vec<String>,
+    /// Drop commits that only touched removed paths and end up with the same
+    /// tree as their parent. Commits that were empty to begin with and merge
+    /// commits are always kept.
+    pub prune_empty: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RemovePathsResult {
+    #[serde(flatten)]
+    pub rewrite: RewriteResult,
+    /// Commits whose tree lost at least one path, pruned ones included.
+    pub commits_affected: usize,
+    /// Distinct blobs that were removed from at least one tree.
+    pub blobs_removed: usize,
+    /// Every removed file path, sorted.
+    pub removed_paths: Vec<String>,
+}
+
+/// Rebuilds trees without the entries matching a pathspec. Results are cached
+/// per (tree, directory) pair, so a subtree shared by many commits is only
+/// filtered once.
+struct PathFilter<'r> {
+    repo: &'r Repository,
+    spec: Pathspec,
+    trees: HashMap<(Oid, String), Oid>,
+    empty_tree: Oid,
+    removed_blobs: HashSet<Oid>,
+    removed_paths: BTreeSet<String>,
+}
+
+impl<'r> PathFilter<'r> {
+    /// Filter `tree`, whose entries live under `prefix` (empty or ending in `/`).
+    fn filter(&mut self, tree: &Tree, prefix: &str) -> Result<Oid, Error> {
+        let key = (tree.id(), prefix.to_string());
+        if let Some(filtered) = self.trees.get(&key) {
+            return Ok(*filtered);
+        }
+
+        let repo = self.repo;
+        let mut builder = None;
+        for entry in tree.iter() {
+            let path = format!("{}{}", prefix, String::from_utf8_lossy(entry.name_bytes()));
+            let replacement = if self
+                .spec
+                .matches_path(Path::new(&path), PathspecFlags::DEFAULT)
+            {
+                self.record_removal(&entry, &path)?;
+                None
+            } else if entry.kind() == Some(ObjectType::Tree) {
+                let filtered = self.filter(&repo.find_tree(entry.id())?, &format!("{}/", path))?;
+                if filtered == entry.id() {
+                    continue;
+                }
+                // Git does not store empty directories
+                Some(filtered).filter(|oid| *oid != self.empty_tree)
+            } else {
+                continue;
+            };
+
+            if builder.is_none() {
+                builder = Some(repo.treebuilder(Some(tree))?);
+            }
+            let builder = builder.as_mut().unwrap();
+            match replacement {
+                Some(oid) => {
+                    builder.insert(entry.name_bytes(), oid, entry.filemode())?;
+                }
+                None => builder.remove(entry.name_bytes())?,
+            }
+        }
+
+        let filtered = match builder {
+            Some(builder) => builder.write()?,
+            None => tree.id(),
+        };
+        self.trees.insert(key, filtered);
+        Ok(filtered)
+    }
+
+    fn record_removal(&mut self, entry: &git2::TreeEntry, path: &str) -> Result<(), Error> {
+        if entry.kind() != Some(ObjectType::Tree) {
+            self.removed_blobs.insert(entry.id());
+            self.removed_paths.insert(path.to_string());
+            return Ok(());
+        }
+        let subtree = self.repo.find_tree(entry.id())?;
+        subtree.walk(TreeWalkMode::PreOrder, |dir, child| {
+            if child.kind() != Some(ObjectType::Tree) {
+                self.removed_blobs.insert(child.id());
+                self.removed_paths.insert(format!(
+                    "{}/{}{}",
+                    path,
+                    dir,
+                    String::from_utf8_lossy(child.name_bytes())
+                ));
+            }
+            TreeWalkResult::Ok
+        })?;
+        Ok(())
+    }
+}
+
+/// Remove every path matching `remove.paths` from the tree of every commit in
+/// the selected history, like `git filter-repo --invert-paths`. Trees are
+/// rebuilt with `TreeBuilder`, commits whose tree does not change are only
+/// re-parented, and with `prune_empty` commits left without changes are
+/// dropped. The removed objects stay in the object database until the backup
+/// entries are deleted (see `prune_backups`) and git garbage-collects them.
+pub fn remove_paths(
+    repo: &Repository,
+    remove: &RemovePathsOptions,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RemovePathsResult, Error> {
+    if remove.paths.is_empty() || remove.paths.iter().any(|p| p.trim().is_empty()) {
+        return Err(Error::invalid_input(
+            "paths",
+            "Give at least one non-empty path",
+        ));
+    }
+    let spec = Pathspec::new(remove.paths.iter())
+        .map_err(|e| Error::invalid_input("paths", e.message()))?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let mut filter = PathFilter {
+        repo,
+        spec,
+        trees: HashMap::new(),
+        empty_tree: repo.treebuilder(None)?.write()?,
+        removed_blobs: HashSet::new(),
+        removed_paths: BTreeSet::new(),
+    };
+    let mut commits_affected = 0;
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            let tree = filter.filter(&commit.tree()?, "")?;
+            if tree == commit.tree_id() {
+                return Ok(CommitAction::Keep);
+            }
+            commits_affected += 1;
+
+            if remove.prune_empty && commit.parent_count() <= 1 {
+                let (original_parent_tree, parent_tree) = match commit.parents().next() {
+                    Some(parent) => (parent.tree_id(), filter.filter(&parent.tree()?, "")?),
+                    None => (filter.empty_tree, filter.empty_tree),
+                };
+                // A commit that was empty to begin with stays
+                if tree == parent_tree && commit.tree_id() != original_parent_tree {
+                    return Ok(CommitAction::Drop);
+                }
+            }
+            Ok(CommitAction::Edit {
+                edit: Default::default(),
+                tree: Some(tree),
+            })
+        },
+        "removed paths from history",
+        options,
+        on_progress,
+    )?;
+
+    if commits_affected == 0 {
+        return Err(Error::nothing_to_rewrite(
+            "No commit contains the given paths",
+        ));
+    }
+    let (old_oid, new_oid) = match (outcome.edited.first(), outcome.dropped.first()) {
+        (Some(first), _) => (first.old_oid.clone(), first.new_oid.clone()),
+        (None, Some(dropped)) => (dropped.clone(), String::new()),
+        (None, None) => Default::default(),
+    };
+    Ok(RemovePathsResult {
+        rewrite: outcome.into_result_for(old_oid, new_oid, options.dry_run),
+        commits_affected,
+        blobs_removed: filter.removed_blobs.len(),
+        removed_paths: filter.removed_paths.into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::list_commits;
+    use crate::test_support::{commit_files, create_test_repo};
+
+    fn remove(paths: &[&str], prune_empty: bool) -> RemovePathsOptions {
+        RemovePathsOptions {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            prune_empty,
+        }
+    }
+
+    fn leaky_repo() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = create_test_repo(1);
+        std::fs::create_dir_all(dir.path().join("data/raw")).unwrap();
+        commit_files(
+            &repo,
+            &[(".env", Some(b"SECRET=1\n")), ("app.rs", Some(b"v1\n"))],
+            "Add app",
+        );
+        commit_files(
+            &repo,
+            &[("data/raw/big.bin", Some(b"\0\0\0"))],
+            "Add dataset",
+        );
+        commit_files(&repo, &[("app.rs", Some(b"v2\n"))], "Update app");
+        commit_files(&repo, &[(".env", Some(b"SECRET=2\n"))], "Rotate secret");
+        (dir, repo)
+    }
+
+    fn messages(repo: &Repository) -> Vec<String> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.short_message)
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_paths_from_every_commit() {
+        let (_dir, repo) = leaky_repo();
+        let result = remove_paths(
+            &repo,
+            &remove(&[".env", "data"], false),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.commits_affected, 4);
+        assert_eq!(result.blobs_removed, 3);
+        assert_eq!(result.removed_paths, [".env", "data/raw/big.bin"]);
+        assert!(result.rewrite.dropped_commits.is_empty());
+
+        let mut revwalk = repo.revwalk().unwrap();
+        revwalk.push_head().unwrap();
+        for oid in revwalk {
+            let tree = repo.find_commit(oid.unwrap()).unwrap().tree().unwrap();
+            assert!(tree.get_path(Path::new(".env")).is_err());
+            assert!(tree.get_path(Path::new("data")).is_err());
+        }
+        let tip_tree = repo.head().unwrap().peel_to_tree().unwrap();
+        assert!(tip_tree.get_path(Path::new("app.rs")).is_ok());
+        // The dataset commit and the secret rotation are now empty, but kept
+        assert_eq!(messages(&repo).len(), 5);
+    }
+
+    #[test]
+    fn test_remove_paths_prunes_emptied_commits() {
+        let (_dir, repo) = leaky_repo();
+        let result = remove_paths(
+            &repo,
+            &remove(&[".env", "data/"], true),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.rewrite.dropped_commits.len(), 2);
+        assert_eq!(messages(&repo), ["Update app", "Add app", "Commit 0"]);
+    }
+
+    #[test]
+    fn test_remove_paths_keeps_originally_empty_commits() {
+        let (_dir, repo) = leaky_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let sig = git2::Signature::now("Test User", "test@example.com").unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            "Empty marker",
+            &head.tree().unwrap(),
+            &[&head],
+        )
+        .unwrap();
+        drop(head);
+
+        let result = remove_paths(
+            &repo,
+            &remove(&[".env", "data/"], true),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(result.rewrite.dropped_commits.len(), 2);
+        assert_eq!(
+            messages(&repo),
+            ["Empty marker", "Update app", "Add app", "Commit 0"]
+        );
+    }
+
+    #[test]
+    fn test_remove_paths_dry_run_and_no_match() {
+        let (_dir, repo) = leaky_repo();
+        let head = repo.head().unwrap().target();
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let preview = remove_paths(&repo, &remove(&["*.bin"], false), &dry, &|_, _| {}).unwrap();
+        assert_eq!(preview.commits_affected, 3);
+        assert_eq!(preview.removed_paths, ["data/raw/big.bin"]);
+        assert_eq!(repo.head().unwrap().target(), head);
+
+        let err = remove_paths(&repo, &remove(&["*.csv"], false), &dry, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "nothing_to_rewrite");
+    }
+}
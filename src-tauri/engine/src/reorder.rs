@@ -0,0 +1,264 @@
+use crate::error::Error;
+use crate::rewrite::{
+    execute_rewrite, linear_run, plan_rewrite, replay_tree, CommitAction, CommitEdit,
+    RewriteOptions, RewriteResult,
+};
+use git2::{Oid, Repository};
+use std::collections::HashMap;
+
+/// Reorder a run of consecutive non-merge commits, like moving lines in an
+/// interactive rebase. `new_order` lists the commits of the run oldest first,
+/// in the order they should end up in. Each moved commit is cherry-picked in
+/// memory onto the previous one; if any of them conflicts the whole operation
+/// fails with `MergeConflict` before a single commit or ref is written (merged
+/// trees may be left in the object database). Commits above the run are
+/// recreated on top of the new tip, so the final tree is unchanged.
+pub fn reorder_commits(
+    repo: &Repository,
+    new_order: &[Oid],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let plan = plan_rewrite(repo, &options.refs)?;
+    let run = linear_run(repo, new_order, &plan, &options.refs, "reorder")?;
+    let first_moved = run
+        .iter()
+        .zip(new_order)
+        .position(|(old, new)| old != new)
+        .ok_or_else(|| Error::nothing_to_rewrite("The commits are already in this order"))?;
+
+    // Replay the moved commits in their new order. The walk keeps the original
+    // positions, so the commit at each position takes over the metadata and
+    // tree of the commit that moves there.
+    let first = repo.find_commit(run[first_moved])?;
+    let base_parent = first.parent_ids().next();
+    let mut tree = match base_parent {
+        Some(parent) => repo.find_commit(parent)?.tree()?,
+        None => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+    };
+    let mut replacements: HashMap<Oid, (Oid, CommitEdit, Oid)> = HashMap::new();
+    for (position, source) in run.iter().zip(new_order).skip(first_moved) {
+        let commit = repo.find_commit(*source)?;
+        let tree_oid = replay_tree(repo, &commit, &tree)?;
+        tree = repo.find_tree(tree_oid)?;
+        replacements.insert(*position, (*source, CommitEdit::copy_of(&commit), tree_oid));
+    }
+
+    let description = format!("reordered {} commits", run.len() - first_moved);
+    let mut outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            Ok(match replacements.get(&commit.id()) {
+                Some((source, edit, tree)) => CommitAction::Replace {
+                    source: *source,
+                    edit: edit.clone(),
+                    tree: *tree,
+                },
+                None => CommitAction::Keep,
+            })
+        },
+        &description,
+        options,
+        on_progress,
+    )?;
+
+    // Report the moved commits under their own OIDs rather than their positions
+    let sources: HashMap<String, (Oid, Oid)> = replacements
+        .iter()
+        .map(|(position, (source, _, tree))| (position.to_string(), (*source, *tree)))
+        .collect();
+    let mut new_parent = base_parent.map(|p| p.to_string());
+    for change in outcome.changes.iter_mut() {
+        let Some((source, tree)) = sources.get(&change.old_oid).copied() else {
+            continue;
+        };
+        let source_commit = repo.find_commit(source)?;
+        let mut changed_fields = Vec::new();
+        if tree != source_commit.tree_id() {
+            changed_fields.push("tree".to_string());
+        }
+        if source_commit.parent_ids().next().map(|p| p.to_string()) != new_parent {
+            changed_fields.push("parents".to_string());
+        }
+        new_parent = Some(change.new_oid.clone());
+        change.old_oid = source.to_string();
+        change.changed_fields = changed_fields;
+    }
+    let old_oids = outcome
+        .edited
+        .iter_mut()
+        .map(|mapping| &mut mapping.old_oid)
+        .chain(outcome.resigned.iter_mut())
+        .chain(outcome.lost_signatures.iter_mut());
+    for oid in old_oids {
+        if let Some((source, _)) = sources.get(oid.as_str()) {
+            *oid = source.to_string();
+        }
+    }
+
+    outcome.into_result(None, options.dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::list_backup_entries;
+    use crate::repo::list_commits;
+    use crate::test_support::{add_signed_commit, commit_files, create_test_repo, FakeSigner};
+    use std::sync::Arc;
+
+    fn oids_newest_first(repo: &Repository) -> Vec<Oid> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .iter()
+            .map(|c| Oid::from_str(&c.oid).unwrap())
+            .collect()
+    }
+
+    fn messages(repo: &Repository) -> Vec<String> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.short_message)
+            .collect()
+    }
+
+    #[test]
+    fn test_reorder_moves_commits_and_keeps_final_tree() {
+        let (_dir, repo) = create_test_repo(5);
+        let oids = oids_newest_first(&repo);
+        let tip_tree = repo.find_commit(oids[0]).unwrap().tree_id();
+
+        // Commits 1, 2, 3 become 3, 1, 2
+        let result = reorder_commits(
+            &repo,
+            &[oids[1], oids[3], oids[2]],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(
+            messages(&repo),
+            ["Commit 4", "Commit 2", "Commit 1", "Commit 3", "Commit 0"]
+        );
+        let new_tip = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(new_tip.tree_id(), tip_tree);
+        assert_eq!(result.old_oid, oids[1].to_string());
+        assert_eq!(result.edited_commits.len(), 3);
+
+        let moved = result
+            .commits
+            .iter()
+            .find(|c| c.old_oid == oids[1].to_string())
+            .unwrap();
+        assert_eq!(moved.changed_fields, ["tree", "parents"]);
+        // Commit 4 only moves because its parent changed
+        let tip_change = result
+            .commits
+            .iter()
+            .find(|c| c.old_oid == oids[0].to_string())
+            .unwrap();
+        assert_eq!(tip_change.changed_fields, ["parents"]);
+    }
+
+    #[test]
+    fn test_reorder_conflict_leaves_refs_untouched() {
+        let (_dir, repo) = create_test_repo(1);
+        let first = commit_files(
+            &repo,
+            &[("file_0.txt", Some(b"first edit\n"))],
+            "First edit",
+        );
+        let second = commit_files(
+            &repo,
+            &[("file_0.txt", Some(b"second edit\n"))],
+            "Second edit",
+        );
+
+        let err = reorder_commits(
+            &repo,
+            &[second, first],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        match &err {
+            Error::MergeConflict { oid, paths } => {
+                assert_eq!(oid, &second.to_string());
+                assert_eq!(paths, &["file_0.txt"]);
+            }
+            other => panic!("expected a merge conflict, got {:?}", other),
+        }
+        assert_eq!(repo.head().unwrap().target(), Some(second));
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reorder_dry_run_and_unchanged_order() {
+        let (_dir, repo) = create_test_repo(3);
+        let oids = oids_newest_first(&repo);
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let preview = reorder_commits(&repo, &[oids[1], oids[2]], &dry, &|_, _| {}).unwrap();
+        assert_eq!(oids_newest_first(&repo), oids);
+        let real = reorder_commits(
+            &repo,
+            &[oids[1], oids[2]],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(
+            preview.updated_refs[0].new_oid,
+            real.updated_refs[0].new_oid
+        );
+
+        let oids = oids_newest_first(&repo);
+        let err = reorder_commits(
+            &repo,
+            &[oids[2], oids[1]],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "nothing_to_rewrite");
+    }
+
+    #[test]
+    fn test_reorder_signature_follows_moved_commit() {
+        let (_dir, repo) = create_test_repo(2);
+        let unsigned = repo.head().unwrap().target().unwrap();
+        let signed = add_signed_commit(&repo);
+
+        let preview_options = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let preview =
+            reorder_commits(&repo, &[signed, unsigned], &preview_options, &|_, _| {}).unwrap();
+        assert_eq!(preview.lost_signatures, [signed.to_string()]);
+
+        let options = RewriteOptions {
+            signer: Some(Arc::new(FakeSigner)),
+            ..Default::default()
+        };
+        let result = reorder_commits(&repo, &[signed, unsigned], &options, &|_, _| {}).unwrap();
+        assert_eq!(result.resigned_commits, [signed.to_string()]);
+        assert!(result.lost_signatures.is_empty());
+
+        // The signed commit now sits below the unsigned one and is still signed
+        assert_eq!(messages(&repo)[..2], ["Commit 1", "Signed commit"]);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert!(repo.extract_signature(&head.id(), None).is_err());
+        assert!(repo
+            .extract_signature(&head.parent_id(0).unwrap(), None)
+            .is_ok());
+    }
+}
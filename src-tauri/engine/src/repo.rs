@@ -0,0 +1,314 @@
+use crate::error::{access_denied, Error, GitErrorInfo};
+use crate::filter::{matches_filters, CommitFilters};
+use crate::trailers::{parse_trailers, Trailer};
+use git2::{Oid, Repository, Sort};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RepoInfo {
+    pub path: String,
+    pub branch: String,
+    pub commit_count: usize,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CommitSummary {
+    pub oid: String,
+    pub short_message: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub author_date: i64,
+}
+
+#[derive(Serialize, Clone)]
+pub struct CommitDetail {
+    pub oid: String,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub author_date: i64,
+    pub author_offset: i32,
+    pub committer_name: String,
+    pub committer_email: String,
+    pub committer_date: i64,
+    pub committer_offset: i32,
+    pub parent_oids: Vec<String>,
+    pub is_merge: bool,
+    /// Trailers of the message (`Signed-off-by`, `Co-authored-by`, ...), in order.
+    pub trailers: Vec<Trailer>,
+}
+
+/// Parse a hex commit OID as sent by the UI or given on the command line.
+pub fn parse_oid(value: &str) -> Result<Oid, Error> {
+    Oid::from_str(value).map_err(|e| Error::InvalidOid {
+        value: value.to_string(),
+        git: GitErrorInfo::from(&e),
+    })
+}
+
+pub fn open_repo(path: &str) -> Result<Repository, Error> {
+    Repository::open(path).map_err(|e| {
+        if e.code() == git2::ErrorCode::NotFound {
+            Error::NotARepository {
+                path: path.to_string(),
+                git: GitErrorInfo::from(&e),
+            }
+        } else if access_denied(&e, Path::new(path), false)
+            || access_denied(&e, &Path::new(path).join(".git"), false)
+        {
+            Error::PermissionDenied {
+                path: Some(path.to_string()),
+                git: GitErrorInfo::from(&e),
+            }
+        } else {
+            Error::from(e)
+        }
+    })
+}
+
+/// Current branch and the number of commits reachable from HEAD.
+pub fn repository_info(repo: &Repository, path: &str) -> Result<RepoInfo, Error> {
+    let head = repo.head().ok();
+
+    let branch = head
+        .as_ref()
+        .and_then(|h| h.shorthand().map(String::from))
+        .unwrap_or_else(|| "HEAD (detached)".to_string());
+
+    let commit_count = if head.is_some() {
+        let mut revwalk = repo.revwalk()?;
+        match revwalk.push_head() {
+            Ok(_) => revwalk.count(),
+            Err(_) => 0,
+        }
+    } else {
+        0
+    };
+
+    Ok(RepoInfo {
+        path: path.to_string(),
+        branch,
+        commit_count,
+    })
+}
+
+/// A page of commits reachable from HEAD, newest first.
+pub fn list_commits(
+    repo: &Repository,
+    offset: usize,
+    limit: usize,
+) -> Result<Vec<CommitSummary>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    if revwalk.push_head().is_err() {
+        return Ok(vec![]);
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let commits: Vec<CommitSummary> = revwalk
+        .skip(offset)
+        .take(limit)
+        .filter_map(|oid| oid.ok())
+        .filter_map(|oid| {
+            let commit = repo.find_commit(oid).ok()?;
+            let message = commit.message().unwrap_or("").to_string();
+            let short_message = message.lines().next().unwrap_or("").to_string();
+            let short_message = if short_message.len() > 72 {
+                format!("{}...", &short_message[..69])
+            } else {
+                short_message
+            };
+
+            let author = commit.author();
+            let author_name = author.name().unwrap_or("").to_string();
+            let author_email = author.email().unwrap_or("").to_string();
+            let author_date = author.when().seconds();
+
+            Some(CommitSummary {
+                oid: oid.to_string(),
+                short_message,
+                author_name,
+                author_email,
+                author_date,
+            })
+        })
+        .collect();
+
+    Ok(commits)
+}
+
+/// A page of the commits reachable from HEAD that match `filters`; `offset`
+/// counts matching commits only.
+pub fn list_commits_filtered(
+    repo: &Repository,
+    offset: usize,
+    limit: usize,
+    filters: &CommitFilters,
+) -> Result<Vec<CommitSummary>, Error> {
+    let mut revwalk = repo.revwalk()?;
+    if revwalk.push_head().is_err() {
+        return Ok(vec![]);
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+
+    let mut matched_commits = Vec::new();
+    let mut skipped = 0;
+
+    for oid in revwalk.filter_map(|r| r.ok()) {
+        if matched_commits.len() >= limit {
+            break;
+        }
+
+        let commit = match repo.find_commit(oid) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+
+        // Apply filters
+        if !matches_filters(repo, &commit, filters)? {
+            continue;
+        }
+
+        // Handle pagination offset
+        if skipped < offset {
+            skipped += 1;
+            continue;
+        }
+
+        let message = commit.message().unwrap_or("").to_string();
+        let short_message = message.lines().next().unwrap_or("").to_string();
+        let short_message = if short_message.len() > 72 {
+            format!("{}...", &short_message[..69])
+        } else {
+            short_message
+        };
+
+        let author = commit.author();
+        let author_name = author.name().unwrap_or("").to_string();
+        let author_email = author.email().unwrap_or("").to_string();
+        let author_date = author.when().seconds();
+
+        matched_commits.push(CommitSummary {
+            oid: oid.to_string(),
+            short_message,
+            author_name,
+            author_email,
+            author_date,
+        });
+    }
+
+    Ok(matched_commits)
+}
+
+/// Full metadata of a single commit.
+pub fn commit_detail(repo: &Repository, oid: Oid) -> Result<CommitDetail, Error> {
+    let commit = repo.find_commit(oid)?;
+
+    let parent_oids: Vec<String> = commit.parent_ids().map(|id| id.to_string()).collect();
+    let message = commit.message().unwrap_or("").to_string();
+
+    let author = commit.author();
+    let author_name = author.name().unwrap_or("").to_string();
+    let author_email = author.email().unwrap_or("").to_string();
+    let author_date = author.when().seconds();
+    let author_offset = author.when().offset_minutes();
+
+    let committer = commit.committer();
+    let committer_name = committer.name().unwrap_or("").to_string();
+    let committer_email = committer.email().unwrap_or("").to_string();
+    let committer_date = committer.when().seconds();
+    let committer_offset = committer.when().offset_minutes();
+
+    let is_merge = commit.parent_count() > 1;
+    let trailers = parse_trailers(&message);
+
+    Ok(CommitDetail {
+        oid: oid.to_string(),
+        message,
+        author_name,
+        author_email,
+        author_date,
+        author_offset,
+        committer_name,
+        committer_email,
+        committer_date,
+        committer_offset,
+        parent_oids,
+        is_merge,
+        trailers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_test_repo;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_repository_info_with_commits() {
+        let (dir, repo) = create_test_repo(3);
+        let info = repository_info(&repo, dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(info.commit_count, 3);
+        assert_eq!(info.branch, "main");
+    }
+
+    #[test]
+    fn test_repository_info_empty() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let info = repository_info(&repo, dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(info.commit_count, 0);
+    }
+
+    #[test]
+    fn test_open_repo_not_a_repo() {
+        let dir = TempDir::new().unwrap();
+        let result = open_repo(dir.path().to_str().unwrap());
+        assert!(result.is_err());
+        let err = result.err().unwrap();
+        assert!(
+            matches!(err, Error::NotARepository { .. }),
+            "Unexpected error: {:?}",
+            err
+        );
+        assert!(err.to_string().contains("not a Git repository"));
+    }
+
+    #[test]
+    fn test_list_commits_pagination() {
+        let (_dir, repo) = create_test_repo(5);
+
+        let first_page = list_commits(&repo, 0, 3).unwrap();
+        assert_eq!(first_page.len(), 3);
+
+        let second_page = list_commits(&repo, 3, 3).unwrap();
+        assert_eq!(second_page.len(), 2);
+
+        // No overlap
+        assert_ne!(first_page[2].oid, second_page[0].oid);
+    }
+
+    #[test]
+    fn test_list_commits_empty_repo() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let result = list_commits(&repo, 0, 10);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_commit_detail() {
+        let (_dir, repo) = create_test_repo(1);
+
+        let commits = list_commits(&repo, 0, 10).unwrap();
+        let detail = commit_detail(&repo, Oid::from_str(&commits[0].oid).unwrap()).unwrap();
+
+        assert_eq!(detail.message, "Commit 0");
+        assert_eq!(detail.author_name, "Test User");
+        assert_eq!(detail.author_email, "test@example.com");
+        assert!(!detail.is_merge);
+        assert!(detail.parent_oids.is_empty());
+    }
+}
@@ -0,0 +1,1550 @@
+use crate::backup::{keep_for_recovery, move_refs_with_backup, BackupKind};
+use crate::error::{Error, GitErrorInfo};
+use crate::safety::PublishedCheck;
+use crate::signing::{configured_signer, CommitSigner, SigningMode};
+use crate::tags::{tag_moves, tags_in_history, TagRewrite};
+use crate::trailers::{apply_trailer_edits, TrailerEdit};
+use crate::worktree::{prepare_worktree, DirtyHead};
+use git2::{Oid, Repository, Signature, Sort};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RefUpdate {
+    pub ref_name: String,
+    pub old_oid: String,
+    pub new_oid: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct OidMapping {
+    pub old_oid: String,
+    pub new_oid: String,
+}
+
+/// One rewritten commit and which of its fields differ from the original.
+/// Descendants that only got new parents list just `parents`.
+#[derive(Serialize, Clone, Debug)]
+pub struct CommitChange {
+    pub old_oid: String,
+    pub new_oid: String,
+    pub changed_fields: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RewriteResult {
+    pub old_oid: String,
+    pub new_oid: String,
+    pub commits_rewritten: usize,
+    /// Refs that were moved, or would be moved when `dry_run` is set.
+    pub updated_refs: Vec<RefUpdate>,
+    pub edited_commits: Vec<OidMapping>,
+    /// Every rewritten commit in walk order (oldest first).
+    pub commits: Vec<CommitChange>,
+    pub dry_run: bool,
+    /// Old OIDs of signed commits that were re-signed (or would be, in a dry run).
+    pub resigned_commits: Vec<String>,
+    /// Set on a dry run that would re-sign commits. The signer is not invoked
+    /// then, so the new OIDs of the re-signed commits, of everything built on
+    /// them and of the refs pointing there are computed without the signature
+    /// and will not match the real run.
+    pub approximate_oids: bool,
+    /// Old OIDs of signed commits whose rewritten version is unsigned.
+    pub lost_signatures: Vec<String>,
+    /// Old OIDs of commits that are no longer part of the rewritten history.
+    pub dropped_commits: Vec<String>,
+    /// The auto-stash entry that did not apply cleanly on the new HEAD and was
+    /// left in the stash list.
+    pub unapplied_stash: Option<String>,
+}
+
+/// Which refs a rewrite walks from and moves afterwards.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RefSelection {
+    /// Only the branch HEAD points to (the original behaviour).
+    #[default]
+    Head,
+    /// Every local branch under `refs/heads/`.
+    LocalBranches,
+    /// Every branch and tag, plus any other ref outside `refs/remotes/`,
+    /// `refs/notes/`, `refs/stash` and our own backup refs (like `--all`).
+    All,
+    /// An explicit list of ref names; short names such as `main` or `v1.0` are resolved.
+    Refs(Vec<String>),
+}
+
+/// Settings shared by every history rewrite.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct RewriteOptions {
+    pub refs: RefSelection,
+    /// Compute the new OIDs without writing objects, moving refs or recording a backup.
+    /// Commits that would be re-signed are hashed unsigned; see `RewriteResult::approximate_oids`.
+    pub dry_run: bool,
+    /// How rewritten commits that were signed get re-signed.
+    pub signing: SigningMode,
+    /// Rewrite commits that remote-tracking refs or tags outside the rewrite
+    /// already contain, instead of failing with `AlreadyPublished`.
+    pub allow_published: bool,
+    /// Stash local changes before the working tree is updated to a rewritten
+    /// HEAD and re-apply them afterwards, instead of failing with `DirtyWorktree`.
+    pub auto_stash: bool,
+    /// What happens to tags on rewritten commits.
+    pub tags: TagRewrite,
+    /// Custom signing backend; takes precedence over `signing`.
+    #[serde(skip)]
+    pub signer: Option<Arc<dyn CommitSigner>>,
+}
+
+impl RewriteOptions {
+    fn commit_signer(&self, repo: &Repository) -> Result<Option<Arc<dyn CommitSigner>>, Error> {
+        if let Some(signer) = &self.signer {
+            return Ok(Some(signer.clone()));
+        }
+        match self.signing {
+            SigningMode::Strip => Ok(None),
+            SigningMode::Sign => configured_signer(repo).map(Some),
+        }
+    }
+}
+
+const EDITOR_REF_NAMESPACE: &str = "refs/git-history-editor/";
+
+/// Resolve a `RefSelection` into (full ref name, commit OID) pairs.
+/// Symbolic refs and refs that don't point directly at a commit (e.g. annotated
+/// tags) are skipped.
+fn resolve_ref_selection(
+    repo: &Repository,
+    selection: &RefSelection,
+) -> Result<Vec<(String, Oid)>, Error> {
+    let mut names: Vec<String> = Vec::new();
+
+    match selection {
+        RefSelection::Head => {
+            let head = repo.head()?;
+            if !head.is_branch() {
+                return Err(Error::DetachedHead {
+                    action: "rewrite history".to_string(),
+                });
+            }
+            names.push(
+                head.name()
+                    .ok_or_else(|| Error::Internal("HEAD ref name is not valid UTF-8".to_string()))?
+                    .to_string(),
+            );
+        }
+        RefSelection::LocalBranches | RefSelection::All => {
+            let glob = match selection {
+                RefSelection::LocalBranches => "refs/heads/*",
+                _ => "refs/*",
+            };
+            for reference in repo.references_glob(glob)? {
+                let reference = reference?;
+                let Some(name) = reference.name() else {
+                    continue;
+                };
+                // Notes and stashes point at bookkeeping commits, not at history to rewrite
+                if name.starts_with("refs/remotes/")
+                    || name.starts_with("refs/notes/")
+                    || name == "refs/stash"
+                    || name.starts_with(EDITOR_REF_NAMESPACE)
+                {
+                    continue;
+                }
+                names.push(name.to_string());
+            }
+        }
+        RefSelection::Refs(requested) => {
+            for short_name in requested {
+                let reference =
+                    repo.resolve_reference_from_short_name(short_name)
+                        .map_err(|_| Error::RefNotFound {
+                            ref_name: short_name.clone(),
+                        })?;
+                let name = reference.name().ok_or_else(|| {
+                    Error::invalid_input(
+                        "refs",
+                        format!("Ref '{}' has an invalid name", short_name),
+                    )
+                })?;
+                names.push(name.to_string());
+            }
+        }
+    }
+
+    let mut resolved = Vec::new();
+    for name in names {
+        let reference = repo.find_reference(&name)?;
+        let Some(target) = reference.target() else {
+            continue;
+        };
+        if repo.find_commit(target).is_err() {
+            continue;
+        }
+        if !resolved.iter().any(|(n, _): &(String, Oid)| *n == name) {
+            resolved.push((name, target));
+        }
+    }
+
+    if resolved.is_empty() {
+        return Err(Error::nothing_to_rewrite("No refs to rewrite"));
+    }
+    Ok(resolved)
+}
+
+/// Field overrides for a single commit. `None` keeps the original value.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct CommitEdit {
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub author_date: Option<i64>,
+    pub author_offset: Option<i32>,
+    pub committer_name: Option<String>,
+    pub committer_email: Option<String>,
+    pub committer_date: Option<i64>,
+    pub committer_offset: Option<i32>,
+    pub message: Option<String>,
+    /// Trailer changes, applied after `message` (or to the original message).
+    pub trailers: Vec<TrailerEdit>,
+}
+
+impl CommitEdit {
+    /// An edit that gives a commit `source`'s author, committer and message.
+    pub(crate) fn copy_of(source: &git2::Commit) -> Self {
+        let author = source.author();
+        let committer = source.committer();
+        CommitEdit {
+            author_name: Some(author.name().unwrap_or("").to_string()),
+            author_email: Some(author.email().unwrap_or("").to_string()),
+            author_date: Some(author.when().seconds()),
+            author_offset: Some(author.when().offset_minutes()),
+            committer_name: Some(committer.name().unwrap_or("").to_string()),
+            committer_email: Some(committer.email().unwrap_or("").to_string()),
+            committer_date: Some(committer.when().seconds()),
+            committer_offset: Some(committer.when().offset_minutes()),
+            message: Some(source.message().unwrap_or("").to_string()),
+            trailers: Vec::new(),
+        }
+    }
+}
+
+/// Build a signature from `orig`, replacing whichever parts are overridden.
+fn edited_signature(
+    orig: &Signature,
+    name: Option<&str>,
+    email: Option<&str>,
+    date: Option<i64>,
+    offset: Option<i32>,
+    role: &str,
+) -> Result<Signature<'static>, Error> {
+    let time = git2::Time::new(
+        date.unwrap_or(orig.when().seconds()),
+        offset.unwrap_or(orig.when().offset_minutes()),
+    );
+    Signature::new(
+        name.unwrap_or(orig.name().unwrap_or("")),
+        email.unwrap_or(orig.email().unwrap_or("")),
+        &time,
+    )
+    .map_err(|e| Error::InvalidSignature {
+        field: role.to_string(),
+        git: GitErrorInfo::from(&e),
+    })
+}
+
+/// Rewrite a single commit and every descendant of it.
+/// Walks every ref in `options.refs` with a shared `oid_map` and moves each ref
+/// whose history contains the target, recording a backup entry for them first.
+/// `on_progress` is called with (current_index, total_count) during the walk.
+pub fn rewrite_commit(
+    repo: &Repository,
+    target_oid: Oid,
+    edit: CommitEdit,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    rewrite_commits(repo, &[(target_oid, edit)], options, on_progress)
+}
+
+/// What `execute_rewrite` does with one commit of the walk.
+pub(crate) enum CommitAction {
+    /// Recreate it only if one of its parents was rewritten.
+    Keep,
+    /// Recreate it with `edit` applied and, if set, a different tree.
+    Edit { edit: CommitEdit, tree: Option<Oid> },
+    /// Recreate it as a copy of `source` (a commit moved into its place) with
+    /// `edit` and `tree` applied; whether it is signed follows `source`.
+    Replace {
+        source: Oid,
+        edit: CommitEdit,
+        tree: Oid,
+    },
+    /// Replace it with a chain of commits, oldest first, each with its own edit
+    /// and tree; the last one takes the commit's place.
+    Split(Vec<(CommitEdit, Oid)>),
+    /// Leave it out; its children are reparented onto its own (remapped) parents.
+    Drop,
+}
+
+impl From<Option<CommitEdit>> for CommitAction {
+    fn from(edit: Option<CommitEdit>) -> Self {
+        match edit {
+            Some(edit) => CommitAction::Edit { edit, tree: None },
+            None => CommitAction::Keep,
+        }
+    }
+}
+
+/// The refs a rewrite will move and every commit reachable from them,
+/// oldest first (`TOPOLOGICAL | REVERSE`).
+pub(crate) struct RewritePlan {
+    pub(crate) ref_targets: Vec<(String, Oid)>,
+    pub(crate) oids: Vec<Oid>,
+}
+
+pub(crate) fn plan_rewrite(repo: &Repository, refs: &RefSelection) -> Result<RewritePlan, Error> {
+    let ref_targets = resolve_ref_selection(repo, refs)?;
+
+    let mut revwalk = repo.revwalk()?;
+    for (_, ref_oid) in &ref_targets {
+        revwalk.push(*ref_oid)?;
+    }
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+
+    let oids: Vec<Oid> = revwalk.filter_map(|r| r.ok()).collect();
+    Ok(RewritePlan { ref_targets, oids })
+}
+
+/// Put `oids` in parent-to-child order and check that they form a single run
+/// of consecutive non-merge commits in `plan`, where nothing but the next
+/// commit of the run builds on any commit below its top. `action` names the
+/// operation in error messages.
+pub(crate) fn linear_run(
+    repo: &Repository,
+    oids: &[Oid],
+    plan: &RewritePlan,
+    refs: &RefSelection,
+    action: &str,
+) -> Result<Vec<Oid>, Error> {
+    let mut requested: HashSet<Oid> = HashSet::new();
+    for oid in oids {
+        if !requested.insert(*oid) {
+            return Err(Error::DuplicateEdit {
+                oid: oid.to_string(),
+            });
+        }
+    }
+    if oids.len() < 2 {
+        return Err(Error::invalid_input(
+            "oids",
+            format!("Select at least two commits to {}", action),
+        ));
+    }
+
+    let run: Vec<Oid> = plan
+        .oids
+        .iter()
+        .copied()
+        .filter(|o| requested.contains(o))
+        .collect();
+    if let Some(missing) = oids.iter().find(|o| !run.contains(o)) {
+        return Err(Error::CommitNotInHistory {
+            oid: missing.to_string(),
+            head_only: matches!(refs, RefSelection::Head),
+        });
+    }
+
+    for (idx, oid) in run.iter().enumerate() {
+        let commit = repo.find_commit(*oid)?;
+        if commit.parent_count() > 1 {
+            return Err(Error::invalid_input(
+                "oids",
+                format!("Cannot {} merge commit {}", action, oid),
+            ));
+        }
+        if idx > 0 && commit.parent_id(0).ok() != Some(run[idx - 1]) {
+            return Err(Error::invalid_input(
+                "oids",
+                format!("Cannot {} commits that are not consecutive", action),
+            ));
+        }
+    }
+
+    let mut children: HashMap<Oid, usize> = HashMap::new();
+    for oid in &plan.oids {
+        for parent in repo.find_commit(*oid)?.parent_ids() {
+            *children.entry(parent).or_default() += 1;
+        }
+    }
+    for oid in &run[..run.len() - 1] {
+        if children.get(oid).copied().unwrap_or(0) > 1 {
+            return Err(Error::invalid_input(
+                "oids",
+                format!("Cannot {}: commit {} has other descendants", action, oid),
+            ));
+        }
+        if let Some((name, _)) = plan.ref_targets.iter().find(|(_, target)| target == oid) {
+            return Err(Error::invalid_input(
+                "oids",
+                format!("Cannot {}: commit {} is the tip of {}", action, oid, name),
+            ));
+        }
+    }
+
+    Ok(run)
+}
+
+/// Tree of `commit`'s changes applied on top of `onto`: a three-way merge with
+/// the commit's first parent (or the empty tree) as the base, like an in-memory
+/// cherry-pick. Fails with `MergeConflict` listing every conflicting path.
+pub(crate) fn replay_tree(
+    repo: &Repository,
+    commit: &git2::Commit,
+    onto: &git2::Tree,
+) -> Result<Oid, Error> {
+    let base = match commit.parent_count() {
+        0 => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+        _ => commit.parent(0)?.tree()?,
+    };
+    merge_tree_changes(repo, commit.id(), &base, onto, &commit.tree()?)
+}
+
+/// Three-way merge of `ours` and `theirs` against `base`, written as a tree.
+/// A conflict fails with `MergeConflict` for `oid`, listing every conflicting path.
+pub(crate) fn merge_tree_changes(
+    repo: &Repository,
+    oid: Oid,
+    base: &git2::Tree,
+    ours: &git2::Tree,
+    theirs: &git2::Tree,
+) -> Result<Oid, Error> {
+    let mut index = repo.merge_trees(base, ours, theirs, None)?;
+    if index.has_conflicts() {
+        let mut paths: Vec<String> = Vec::new();
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+            if let Some(entry) = entry {
+                let path = String::from_utf8_lossy(&entry.path).into_owned();
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        paths.sort();
+        return Err(Error::MergeConflict {
+            oid: oid.to_string(),
+            paths,
+        });
+    }
+    Ok(index.write_tree_to(repo)?)
+}
+
+/// What `execute_rewrite` did: the full old -> new map, the commits that were
+/// edited directly (in walk order) and the refs it moved.
+pub(crate) struct RewriteOutcome {
+    pub(crate) oid_map: HashMap<Oid, Oid>,
+    pub(crate) edited: Vec<OidMapping>,
+    pub(crate) changes: Vec<CommitChange>,
+    pub(crate) commits_rewritten: usize,
+    pub(crate) updated_refs: Vec<RefUpdate>,
+    pub(crate) resigned: Vec<String>,
+    pub(crate) lost_signatures: Vec<String>,
+    pub(crate) dropped: Vec<String>,
+    pub(crate) unapplied_stash: Option<String>,
+}
+
+impl RewriteOutcome {
+    /// Build the command result. `old_oid`/`new_oid` describe `primary`, or
+    /// the first edited commit when `primary` is `None`.
+    pub(crate) fn into_result(
+        self,
+        primary: Option<Oid>,
+        dry_run: bool,
+    ) -> Result<RewriteResult, Error> {
+        let (old_oid, new_oid) = match primary {
+            Some(oid) => {
+                let new = self.oid_map.get(&oid).ok_or_else(|| {
+                    Error::Internal("Target commit was not rewritten".to_string())
+                })?;
+                (oid.to_string(), new.to_string())
+            }
+            None => {
+                let first = self
+                    .edited
+                    .first()
+                    .ok_or_else(|| Error::nothing_to_rewrite("No commits were changed"))?;
+                (first.old_oid.clone(), first.new_oid.clone())
+            }
+        };
+        Ok(self.into_result_for(old_oid, new_oid, dry_run))
+    }
+
+    /// Build the command result with explicit `old_oid`/`new_oid` values.
+    pub(crate) fn into_result_for(
+        self,
+        old_oid: String,
+        new_oid: String,
+        dry_run: bool,
+    ) -> RewriteResult {
+        RewriteResult {
+            approximate_oids: dry_run && !self.resigned.is_empty(),
+            old_oid,
+            new_oid,
+            commits_rewritten: self.commits_rewritten,
+            updated_refs: self.updated_refs,
+            edited_commits: self.edited,
+            commits: self.changes,
+            dry_run,
+            resigned_commits: self.resigned,
+            lost_signatures: self.lost_signatures,
+            dropped_commits: self.dropped,
+            unapplied_stash: self.unapplied_stash,
+        }
+    }
+}
+
+fn changed_signature_fields(
+    role: &str,
+    orig: &Signature,
+    new: &Signature,
+    fields: &mut Vec<String>,
+) {
+    if orig.name_bytes() != new.name_bytes() {
+        fields.push(format!("{}_name", role));
+    }
+    if orig.email_bytes() != new.email_bytes() {
+        fields.push(format!("{}_email", role));
+    }
+    if orig.when().seconds() != new.when().seconds() {
+        fields.push(format!("{}_date", role));
+    }
+    if orig.when().offset_minutes() != new.when().offset_minutes() {
+        fields.push(format!("{}_offset", role));
+    }
+}
+
+/// OID the commit would get, computed from its serialized form without writing
+/// it. `commit_create_buffer` needs an existing tree and parents, so the buffer
+/// is built against the original ones and the `tree` and `parent` lines are
+/// replaced afterwards.
+fn dry_run_commit_oid(
+    repo: &Repository,
+    author: &Signature,
+    committer: &Signature,
+    message: &str,
+    tree_oid: Oid,
+    original: &git2::Commit,
+    new_parent_oids: &[Oid],
+) -> Result<Oid, Error> {
+    let original_parents: Vec<git2::Commit> = original.parents().collect();
+    let parent_refs: Vec<&git2::Commit> = original_parents.iter().collect();
+    let buffer =
+        repo.commit_create_buffer(author, committer, message, &original.tree()?, &parent_refs)?;
+
+    let split = buffer
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .ok_or_else(|| Error::Internal("Malformed commit buffer".to_string()))?;
+    let (header, body) = buffer.split_at(split);
+    let mut rebuilt: Vec<u8> = Vec::with_capacity(buffer.len());
+    for line in header.split(|b| *b == b'\n') {
+        if line.starts_with(b"parent ") {
+            continue;
+        }
+        if !rebuilt.is_empty() {
+            rebuilt.push(b'\n');
+        }
+        // The parents follow the tree line, which always comes first
+        if line.starts_with(b"tree ") {
+            rebuilt.extend_from_slice(format!("tree {}", tree_oid).as_bytes());
+            for parent in new_parent_oids {
+                rebuilt.extend_from_slice(format!("\nparent {}", parent).as_bytes());
+            }
+        } else {
+            rebuilt.extend_from_slice(line);
+        }
+    }
+    rebuilt.extend_from_slice(body);
+
+    Ok(Oid::hash_object(git2::ObjectType::Commit, &rebuilt)?)
+}
+
+/// The shared rewrite walk. `action_for` decides what happens to each commit;
+/// descendants of edited or dropped commits are recreated with remapped
+/// parents. Afterwards every planned ref whose tip changed is moved (a ref on a
+/// dropped commit moves to that commit's first remaining parent), with one backup entry
+/// (labelled `description`) recording all of them. With `dry_run` nothing is
+/// written and the outcome describes what would have happened; the signer is
+/// not invoked then, so re-signed commits and their descendants get their
+/// final OIDs only for real (`RewriteResult::approximate_oids` says so).
+pub(crate) fn execute_rewrite(
+    repo: &Repository,
+    plan: &RewritePlan,
+    action_for: &mut dyn FnMut(&git2::Commit) -> Result<CommitAction, Error>,
+    description: &str,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteOutcome, Error> {
+    let mut oid_map: HashMap<Oid, Oid> = HashMap::new();
+    // Dropped commit -> the (remapped) parents that take its place
+    let mut dropped: HashMap<Oid, Vec<Oid>> = HashMap::new();
+    let mut dropped_commits = Vec::new();
+    let mut edited = Vec::new();
+    let mut changes = Vec::new();
+    let mut resigned = Vec::new();
+    let mut lost_signatures = Vec::new();
+    let signer = options.commit_signer(repo)?;
+    let mut published: Option<PublishedCheck> = None;
+    let tags = tags_in_history(repo, &plan.oids, &options.tags)?;
+    let dirty_head = if options.auto_stash || options.dry_run {
+        None
+    } else {
+        DirtyHead::find(repo, &plan.ref_targets)?
+    };
+    let mut commits_rewritten: usize = 0;
+    let total = plan.oids.len();
+
+    for (idx, current_oid) in plan.oids.iter().enumerate() {
+        if idx % 100 == 0 {
+            on_progress(idx, total);
+        }
+        let commit = repo.find_commit(*current_oid)?;
+
+        // Check if any parent was rewritten or if this commit is edited
+        let action = action_for(&commit)?;
+        let has_rewritten_parent = commit
+            .parent_ids()
+            .any(|pid| oid_map.contains_key(&pid) || dropped.contains_key(&pid));
+
+        if matches!(action, CommitAction::Keep) && !has_rewritten_parent {
+            continue;
+        }
+
+        // Only the oldest changed commits need checking: anything that
+        // contains a descendant also contains them
+        if !has_rewritten_parent && !options.allow_published {
+            if published.is_none() {
+                // Tags that come along with the rewrite do not count either
+                let mut rewritten_refs = plan.ref_targets.clone();
+                rewritten_refs.extend(tags.iter().map(|tag| (tag.ref_name.clone(), tag.commit)));
+                published = Some(PublishedCheck::new(repo, &rewritten_refs)?);
+            }
+            let refs = published.as_ref().unwrap().containing(repo, *current_oid)?;
+            if !refs.is_empty() {
+                return Err(Error::AlreadyPublished {
+                    oid: current_oid.to_string(),
+                    refs,
+                });
+            }
+        }
+
+        // Remap parents; a dropped parent is replaced by its own parents
+        let mut new_parent_oids: Vec<Oid> = Vec::new();
+        for pid in commit.parent_ids() {
+            let mapped = match (oid_map.get(&pid), dropped.get(&pid)) {
+                (Some(new), _) => vec![*new],
+                (None, Some(replacements)) => replacements.clone(),
+                (None, None) => vec![pid],
+            };
+            for oid in mapped {
+                if !new_parent_oids.contains(&oid) {
+                    new_parent_oids.push(oid);
+                }
+            }
+        }
+
+        // The commit whose signature the result inherits
+        let mut signature_source = *current_oid;
+        let (is_edited, parts) = match action {
+            CommitAction::Keep => (false, vec![(CommitEdit::default(), None)]),
+            CommitAction::Edit { edit, tree } => {
+                // Filtered trees are final, so a local change they overwrite can stop the walk now
+                if let (Some(dirty_head), Some(tree)) = (&dirty_head, tree) {
+                    dirty_head.check(&commit, tree)?;
+                }
+                (true, vec![(edit, tree)])
+            }
+            CommitAction::Replace { source, edit, tree } => {
+                signature_source = source;
+                (true, vec![(edit, Some(tree))])
+            }
+            CommitAction::Split(parts) if parts.is_empty() => {
+                return Err(Error::Internal(
+                    "A commit cannot be split into nothing".to_string(),
+                ));
+            }
+            CommitAction::Split(parts) => (
+                true,
+                parts
+                    .into_iter()
+                    .map(|(edit, tree)| (edit, Some(tree)))
+                    .collect(),
+            ),
+            CommitAction::Drop => {
+                dropped.insert(*current_oid, new_parent_oids);
+                dropped_commits.push(current_oid.to_string());
+                continue;
+            }
+        };
+
+        // Signed commits are re-signed when a signer is configured, otherwise the signature is lost
+        let was_signed = repo.extract_signature(&signature_source, None).is_ok();
+        let resign_with = signer.as_ref().filter(|_| was_signed);
+        if was_signed {
+            match resign_with {
+                Some(_) => resigned.push(current_oid.to_string()),
+                None => lost_signatures.push(current_oid.to_string()),
+            }
+        }
+
+        // Each part is created on top of the previous one; the last takes the commit's place
+        let mut parent_oids = new_parent_oids;
+        for (part, (edit, new_tree)) in parts.into_iter().enumerate() {
+            let author = edited_signature(
+                &commit.author(),
+                edit.author_name.as_deref(),
+                edit.author_email.as_deref(),
+                edit.author_date,
+                edit.author_offset,
+                "author",
+            )?;
+            let committer = edited_signature(
+                &commit.committer(),
+                edit.committer_name.as_deref(),
+                edit.committer_email.as_deref(),
+                edit.committer_date,
+                edit.committer_offset,
+                "committer",
+            )?;
+            let message = apply_trailer_edits(
+                edit.message
+                    .as_deref()
+                    .unwrap_or(commit.message().unwrap_or("")),
+                &edit.trailers,
+            );
+
+            let tree_oid = new_tree.unwrap_or(commit.tree_id());
+
+            let mut changed_fields = Vec::new();
+            changed_signature_fields("author", &commit.author(), &author, &mut changed_fields);
+            changed_signature_fields(
+                "committer",
+                &commit.committer(),
+                &committer,
+                &mut changed_fields,
+            );
+            if commit.message_bytes() != message.as_bytes() {
+                changed_fields.push("message".to_string());
+            }
+            if tree_oid != commit.tree_id() {
+                changed_fields.push("tree".to_string());
+            }
+            if has_rewritten_parent || part > 0 {
+                changed_fields.push("parents".to_string());
+            }
+
+            // Create the new commit
+            let new_oid = if options.dry_run {
+                // A dry run may only have hashed the new tree, not written it
+                dry_run_commit_oid(
+                    repo,
+                    &author,
+                    &committer,
+                    &message,
+                    tree_oid,
+                    &commit,
+                    &parent_oids,
+                )?
+            } else {
+                let tree = repo.find_tree(tree_oid)?;
+                let new_parents: Vec<git2::Commit> = parent_oids
+                    .iter()
+                    .map(|pid| repo.find_commit(*pid))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let parent_refs: Vec<&git2::Commit> = new_parents.iter().collect();
+                match resign_with {
+                    Some(signer) => {
+                        let buffer = repo.commit_create_buffer(
+                            &author,
+                            &committer,
+                            &message,
+                            &tree,
+                            &parent_refs,
+                        )?;
+                        let content =
+                            std::str::from_utf8(&buffer).map_err(|_| Error::SigningFailed {
+                                oid: current_oid.to_string(),
+                                message: "commit is not valid UTF-8".to_string(),
+                            })?;
+                        let signature = signer.sign(content).map_err(|e| match e {
+                            Error::SigningFailed { message, .. } => Error::SigningFailed {
+                                oid: current_oid.to_string(),
+                                message,
+                            },
+                            e => e,
+                        })?;
+                        repo.commit_signed(content, &signature, None)?
+                    }
+                    None => {
+                        repo.commit(None, &author, &committer, &message, &tree, &parent_refs)?
+                    }
+                }
+            };
+
+            commits_rewritten += 1;
+            changes.push(CommitChange {
+                old_oid: current_oid.to_string(),
+                new_oid: new_oid.to_string(),
+                changed_fields,
+            });
+            if is_edited {
+                edited.push(OidMapping {
+                    old_oid: current_oid.to_string(),
+                    new_oid: new_oid.to_string(),
+                });
+            }
+            parent_oids = vec![new_oid];
+        }
+        oid_map.insert(*current_oid, parent_oids[0]);
+    }
+
+    // Record one backup entry for every selected ref whose tip was rewritten, then move them
+    let mut moves: Vec<(String, Oid, Oid)> = Vec::new();
+    for (name, old_tip) in &plan.ref_targets {
+        // Selected tags are handled with the others below
+        if options.tags != TagRewrite::Keep && name.starts_with("refs/tags/") {
+            continue;
+        }
+        let new_tip = match (oid_map.get(old_tip), dropped.get(old_tip)) {
+            (Some(new_tip), _) => *new_tip,
+            (None, Some(replacements)) => *replacements.first().ok_or_else(|| {
+                Error::invalid_input(
+                    "refs",
+                    format!("Ref {} would be left without any commits", name),
+                )
+            })?,
+            (None, None) => continue,
+        };
+        moves.push((name.clone(), *old_tip, new_tip));
+    }
+    let new_commit = |oid: Oid| {
+        oid_map
+            .get(&oid)
+            .or_else(|| {
+                dropped
+                    .get(&oid)
+                    .and_then(|replacements| replacements.first())
+            })
+            .copied()
+    };
+    moves.extend(tag_moves(
+        repo,
+        &tags,
+        &options.tags,
+        &new_commit,
+        options.dry_run,
+    )?);
+    let mut unapplied_stash = None;
+    if !moves.is_empty() && !options.dry_run {
+        // Bring the working tree to the new HEAD first, so a dirty file stops the rewrite
+        let sync = prepare_worktree(repo, &moves, options.auto_stash)?;
+        // Renamed tag copies are created, so they must not exist yet
+        let expected: Vec<Option<Oid>> = moves
+            .iter()
+            .map(|(_, old_tip, _)| (!old_tip.is_zero()).then_some(*old_tip))
+            .collect();
+        let moved = match move_refs_with_backup(
+            repo,
+            BackupKind::Rewrite,
+            description,
+            &moves,
+            &expected,
+        ) {
+            Ok(moved) => moved,
+            Err(e) => {
+                if let Some(sync) = sync {
+                    sync.roll_back()?;
+                }
+                return Err(e);
+            }
+        };
+        if let Some(ref_name) = moved {
+            if let Some(sync) = sync {
+                sync.roll_back()?;
+            }
+            // A ref moved since the walk started (a commit from a terminal, a
+            // fetch), so nothing was written; keep the rewritten commits reachable
+            return Err(Error::RefMoved {
+                ref_name,
+                recovery_refs: keep_for_recovery(repo, &moves)?,
+            });
+        }
+        if let Some(sync) = sync {
+            unapplied_stash = sync.finish()?;
+        }
+    }
+
+    let updated_refs = moves
+        .into_iter()
+        .map(|(ref_name, old_tip, new_tip)| RefUpdate {
+            ref_name,
+            old_oid: old_tip.to_string(),
+            new_oid: new_tip.to_string(),
+        })
+        .collect();
+
+    Ok(RewriteOutcome {
+        oid_map,
+        edited,
+        changes,
+        commits_rewritten,
+        updated_refs,
+        resigned,
+        lost_signatures,
+        dropped: dropped_commits,
+        unapplied_stash,
+    })
+}
+
+/// Apply many commit edits in a single `TOPOLOGICAL | REVERSE` walk.
+/// The rewrite gets one backup entry, and the result lists the old and new
+/// OID of each edited commit. `old_oid`/`new_oid` on the result describe the
+/// first entry of `edits`.
+pub fn rewrite_commits(
+    repo: &Repository,
+    edits: &[(Oid, CommitEdit)],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let (first_oid, _) = edits
+        .first()
+        .ok_or_else(|| Error::nothing_to_rewrite("No commits to rewrite"))?;
+
+    let mut edit_map: HashMap<Oid, &CommitEdit> = HashMap::new();
+    for (target_oid, edit) in edits {
+        if edit_map.insert(*target_oid, edit).is_some() {
+            return Err(Error::DuplicateEdit {
+                oid: target_oid.to_string(),
+            });
+        }
+    }
+
+    let refs = &options.refs;
+    let plan = plan_rewrite(repo, refs)?;
+
+    // Check that every target commit is in this history
+    let in_history: HashSet<Oid> = plan.oids.iter().copied().collect();
+    if let Some(missing) = edit_map.keys().find(|o| !in_history.contains(o)) {
+        return Err(Error::CommitNotInHistory {
+            oid: missing.to_string(),
+            head_only: matches!(refs, RefSelection::Head),
+        });
+    }
+
+    let description = if edits.len() == 1 {
+        format!("rewrote commit {}", &first_oid.to_string()[..8])
+    } else {
+        format!("rewrote {} commits", edits.len())
+    };
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| Ok(edit_map.get(&commit.id()).map(|e| (*e).clone()).into()),
+        &description,
+        options,
+        on_progress,
+    )?;
+
+    let edited_commits = edits
+        .iter()
+        .map(|(old, _)| {
+            let new = outcome
+                .oid_map
+                .get(old)
+                .ok_or_else(|| Error::Internal("Target commit was not rewritten".to_string()))?;
+            Ok(OidMapping {
+                old_oid: old.to_string(),
+                new_oid: new.to_string(),
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut result = outcome.into_result(Some(*first_oid), options.dry_run)?;
+    result.edited_commits = edited_commits;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::list_backup_entries;
+    use crate::repo::{commit_detail, list_commits};
+    use crate::test_support::{add_signed_commit, create_test_repo, FakeSigner};
+
+    #[test]
+    fn test_rewrite_commit_changes_message() {
+        let (_dir, repo) = create_test_repo(3);
+
+        let commits = list_commits(&repo, 0, 10).unwrap();
+        // Get the oldest commit (last in the list since sorted newest-first)
+        let oldest = &commits[2];
+
+        let target_oid = Oid::from_str(&oldest.oid).unwrap();
+        let result = rewrite_commit(
+            &repo,
+            target_oid,
+            CommitEdit {
+                message: Some("New message".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.commits_rewritten, 3); // Rewrites oldest + 2 descendants
+
+        // Verify the new commit has the changed message
+        let detail = commit_detail(&repo, Oid::from_str(&result.new_oid).unwrap()).unwrap();
+        assert_eq!(detail.message, "New message");
+    }
+
+    #[test]
+    fn test_rewrite_commit_changes_author() {
+        let (_dir, repo) = create_test_repo(2);
+
+        let commits = list_commits(&repo, 0, 10).unwrap();
+        let latest = &commits[0];
+
+        let target_oid = Oid::from_str(&latest.oid).unwrap();
+        let result = rewrite_commit(
+            &repo,
+            target_oid,
+            CommitEdit {
+                author_name: Some("New Author".to_string()),
+                author_email: Some("new@example.com".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.commits_rewritten, 1); // Only HEAD commit, no descendants
+
+        let detail = commit_detail(&repo, Oid::from_str(&result.new_oid).unwrap()).unwrap();
+        assert_eq!(detail.author_name, "New Author");
+        assert_eq!(detail.author_email, "new@example.com");
+    }
+
+    #[test]
+    fn test_rewrite_preserves_descendants() {
+        let (_dir, repo) = create_test_repo(3);
+
+        let commits_before = list_commits(&repo, 0, 10).unwrap();
+        let oldest = &commits_before[2];
+
+        let target_oid = Oid::from_str(&oldest.oid).unwrap();
+        rewrite_commit(
+            &repo,
+            target_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        // Reload commits after rewrite
+        let commits_after = list_commits(&repo, 0, 10).unwrap();
+        assert_eq!(commits_after.len(), 3);
+
+        // All OIDs should be different (rewritten)
+        for (before, after) in commits_before.iter().zip(commits_after.iter()) {
+            assert_ne!(before.oid, after.oid);
+        }
+
+        // But messages of non-target commits should be preserved
+        assert_eq!(commits_after[0].short_message, "Commit 2");
+        assert_eq!(commits_after[1].short_message, "Commit 1");
+        assert_eq!(commits_after[2].short_message, "Changed root");
+    }
+
+    #[test]
+    fn test_rewrite_head_leaves_other_branches() {
+        let (_dir, repo) = create_test_repo(3);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        repo.branch("feature", &repo.find_commit(head_oid).unwrap(), false)
+            .unwrap();
+
+        let root_oid = repo
+            .find_commit(head_oid)
+            .unwrap()
+            .parent(0)
+            .unwrap()
+            .parent_id(0)
+            .unwrap();
+        let result = rewrite_commit(
+            &repo,
+            root_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.updated_refs.len(), 1);
+        assert_eq!(result.updated_refs[0].ref_name, "refs/heads/main");
+        let feature = repo.find_reference("refs/heads/feature").unwrap();
+        assert_eq!(feature.target(), Some(head_oid));
+    }
+
+    #[test]
+    fn test_rewrite_local_branches_shares_oid_map() {
+        let (_dir, repo) = create_test_repo(3);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let middle_oid = repo.find_commit(head_oid).unwrap().parent_id(0).unwrap();
+        let root_oid = repo.find_commit(middle_oid).unwrap().parent_id(0).unwrap();
+        repo.branch("feature", &repo.find_commit(middle_oid).unwrap(), false)
+            .unwrap();
+
+        let result = rewrite_commit(
+            &repo,
+            root_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions {
+                refs: RefSelection::LocalBranches,
+                ..Default::default()
+            },
+            &|_, _| {},
+        )
+        .unwrap();
+
+        // Each commit is rewritten once even though both branches contain it
+        assert_eq!(result.commits_rewritten, 3);
+        assert_eq!(result.updated_refs.len(), 2);
+
+        let main_tip = repo
+            .find_reference("refs/heads/main")
+            .unwrap()
+            .target()
+            .unwrap();
+        let feature_tip = repo
+            .find_reference("refs/heads/feature")
+            .unwrap()
+            .target()
+            .unwrap();
+        assert_eq!(
+            repo.find_commit(main_tip).unwrap().parent_id(0).unwrap(),
+            feature_tip
+        );
+
+        let feature_update = result
+            .updated_refs
+            .iter()
+            .find(|u| u.ref_name == "refs/heads/feature")
+            .unwrap();
+        assert_eq!(feature_update.old_oid, middle_oid.to_string());
+        assert_eq!(feature_update.new_oid, feature_tip.to_string());
+
+        // Both moved branches are saved in one backup entry
+        let entries = list_backup_entries(&repo).unwrap();
+        assert_eq!(entries.len(), 1);
+        let feature_backup = entries[0]
+            .refs
+            .iter()
+            .find(|r| r.ref_name == "refs/heads/feature")
+            .unwrap();
+        assert_eq!(feature_backup.backup_oid, middle_oid.to_string());
+    }
+
+    #[test]
+    fn test_rewrite_all_moves_tags_containing_target() {
+        let (_dir, repo) = create_test_repo(3);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let middle_oid = repo.find_commit(head_oid).unwrap().parent_id(0).unwrap();
+        let middle = repo.find_object(middle_oid, None).unwrap();
+        repo.tag_lightweight("before", &middle, false).unwrap();
+        repo.tag_lightweight("at-head", &repo.find_object(head_oid, None).unwrap(), false)
+            .unwrap();
+
+        let result = rewrite_commit(
+            &repo,
+            head_oid,
+            CommitEdit {
+                message: Some("Changed head".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions {
+                refs: RefSelection::All,
+                ..Default::default()
+            },
+            &|_, _| {},
+        )
+        .unwrap();
+
+        let mut moved: Vec<&str> = result
+            .updated_refs
+            .iter()
+            .map(|u| u.ref_name.as_str())
+            .collect();
+        moved.sort();
+        assert_eq!(moved, vec!["refs/heads/main", "refs/tags/at-head"]);
+        assert_eq!(
+            repo.find_reference("refs/tags/before").unwrap().target(),
+            Some(middle_oid)
+        );
+    }
+
+    #[test]
+    fn test_rewrite_all_leaves_notes_and_stash_alone() {
+        let (dir, mut repo) = create_test_repo(2);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let sig = git2::Signature::now("Test User", "test@example.com").unwrap();
+        repo.note(&sig, &sig, None, head_oid, "A note", false)
+            .unwrap();
+        let notes_oid = repo.refname_to_id("refs/notes/commits").unwrap();
+        std::fs::write(dir.path().join("file_1.txt"), "local change").unwrap();
+        let stash_oid = repo.stash_save(&sig, "WIP", None).unwrap();
+
+        let result = rewrite_commit(
+            &repo,
+            head_oid,
+            CommitEdit {
+                message: Some("Changed head".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions {
+                refs: RefSelection::All,
+                ..Default::default()
+            },
+            &|_, _| {},
+        )
+        .unwrap();
+
+        let moved: Vec<&str> = result
+            .updated_refs
+            .iter()
+            .map(|u| u.ref_name.as_str())
+            .collect();
+        assert_eq!(moved, vec!["refs/heads/main"]);
+        assert_eq!(repo.refname_to_id("refs/stash").unwrap(), stash_oid);
+        assert_eq!(repo.refname_to_id("refs/notes/commits").unwrap(), notes_oid);
+    }
+
+    #[test]
+    fn test_rewrite_keeps_branch_moved_during_rewrite() {
+        let (_dir, repo) = create_test_repo(2);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let edit = CommitEdit {
+            message: Some("Rewritten".to_string()),
+            ..Default::default()
+        };
+
+        // Someone commits on the branch while the rewrite is walking
+        let concurrent = std::cell::Cell::new(None);
+        let err = rewrite_commits(
+            &repo,
+            &[(head_oid, edit)],
+            &RewriteOptions::default(),
+            &|_, _| {
+                if concurrent.get().is_none() {
+                    let parent = repo.find_commit(head_oid).unwrap();
+                    let sig = git2::Signature::now("Other", "other@example.com").unwrap();
+                    let tree = parent.tree().unwrap();
+                    let oid = repo
+                        .commit(Some("HEAD"), &sig, &sig, "Concurrent", &tree, &[&parent])
+                        .unwrap();
+                    concurrent.set(Some(oid));
+                }
+            },
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(err.kind(), "ref_moved");
+        assert_eq!(err.ref_name(), Some("refs/heads/main"));
+        assert_eq!(repo.head().unwrap().target(), concurrent.get());
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+
+        let recovery = &err.refs().unwrap()[0];
+        assert!(recovery.starts_with("refs/git-history-editor/recovery/"));
+        let rewritten = repo
+            .find_reference(recovery)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        assert_eq!(rewritten.message(), Some("Rewritten"));
+    }
+
+    #[test]
+    fn test_rewrite_explicit_refs_requires_target_in_history() {
+        let (_dir, repo) = create_test_repo(2);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let root_oid = repo.find_commit(head_oid).unwrap().parent_id(0).unwrap();
+        repo.branch("old", &repo.find_commit(root_oid).unwrap(), false)
+            .unwrap();
+
+        let result = rewrite_commit(
+            &repo,
+            head_oid,
+            CommitEdit {
+                message: Some("Changed head".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions {
+                refs: RefSelection::Refs(vec!["old".to_string()]),
+                ..Default::default()
+            },
+            &|_, _| {},
+        );
+        assert_eq!(
+            result.err(),
+            Some(Error::CommitNotInHistory {
+                oid: head_oid.to_string(),
+                head_only: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_rewrite_commits_batch_single_walk() {
+        let (_dir, repo) = create_test_repo(4);
+        let commits = list_commits(&repo, 0, 10).unwrap();
+
+        let fix_email = |oid: &str| {
+            (
+                Oid::from_str(oid).unwrap(),
+                CommitEdit {
+                    author_email: Some("fixed@example.com".to_string()),
+                    ..Default::default()
+                },
+            )
+        };
+        let edits = vec![fix_email(&commits[3].oid), fix_email(&commits[1].oid)];
+
+        let progress_calls = std::cell::Cell::new(0);
+        let result = rewrite_commits(&repo, &edits, &RewriteOptions::default(), &|_, _| {
+            progress_calls.set(progress_calls.get() + 1);
+        })
+        .unwrap();
+
+        assert_eq!(progress_calls.get(), 1);
+        assert_eq!(result.commits_rewritten, 4);
+        assert_eq!(result.updated_refs.len(), 1);
+        assert_eq!(result.edited_commits.len(), 2);
+        assert_eq!(result.old_oid, commits[3].oid);
+        assert_eq!(result.edited_commits[1].old_oid, commits[1].oid);
+
+        let after = list_commits(&repo, 0, 10).unwrap();
+        let emails: Vec<&str> = after.iter().map(|c| c.author_email.as_str()).collect();
+        assert_eq!(
+            emails,
+            vec![
+                "test@example.com",
+                "fixed@example.com",
+                "test@example.com",
+                "fixed@example.com"
+            ]
+        );
+        assert_eq!(after[1].oid, result.edited_commits[1].new_oid);
+    }
+
+    #[test]
+    fn test_rewrite_commits_applies_trailer_edits() {
+        let (_dir, repo) = create_test_repo(2);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let edit = CommitEdit {
+            message: Some("New subject\n\nBody\n".to_string()),
+            trailers: vec![TrailerEdit::Add {
+                key: "Reviewed-by".to_string(),
+                value: "R <r@example.com>".to_string(),
+            }],
+            ..Default::default()
+        };
+
+        let result = rewrite_commits(
+            &repo,
+            &[(head_oid, edit)],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        let detail = commit_detail(&repo, Oid::from_str(&result.new_oid).unwrap()).unwrap();
+        assert_eq!(
+            detail.message,
+            "New subject\n\nBody\n\nReviewed-by: R <r@example.com>\n"
+        );
+        assert_eq!(detail.trailers.len(), 1);
+        assert_eq!(detail.trailers[0].key, "Reviewed-by");
+    }
+
+    #[test]
+    fn test_rewrite_commits_rejects_duplicates() {
+        let (_dir, repo) = create_test_repo(2);
+        let head_oid = repo.head().unwrap().target().unwrap();
+        let edits = vec![
+            (head_oid, CommitEdit::default()),
+            (head_oid, CommitEdit::default()),
+        ];
+        let err = rewrite_commits(&repo, &edits, &RewriteOptions::default(), &|_, _| {}).err();
+        assert_eq!(
+            err.as_ref().and_then(|e| e.oid()),
+            Some(head_oid.to_string().as_str())
+        );
+        assert_eq!(err.map(|e| e.kind()), Some("duplicate_edit"));
+        let err = rewrite_commits(&repo, &[], &RewriteOptions::default(), &|_, _| {}).err();
+        assert_eq!(err.map(|e| e.kind()), Some("nothing_to_rewrite"));
+    }
+
+    #[test]
+    fn test_dry_run_matches_real_rewrite_without_writing() {
+        let (_dir, repo) = create_test_repo(3);
+        let head_before = repo.head().unwrap().target().unwrap();
+        let middle_oid = repo.find_commit(head_before).unwrap().parent_id(0).unwrap();
+        let edit = || {
+            vec![(
+                middle_oid,
+                CommitEdit {
+                    author_name: Some("Dry Run".to_string()),
+                    message: Some("Previewed".to_string()),
+                    ..Default::default()
+                },
+            )]
+        };
+
+        let preview = rewrite_commits(
+            &repo,
+            &edit(),
+            &RewriteOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert!(preview.dry_run);
+        assert_eq!(preview.commits.len(), 2);
+        assert_eq!(
+            preview.commits[0].changed_fields,
+            vec!["author_name", "message"]
+        );
+        assert_eq!(preview.commits[1].changed_fields, vec!["parents"]);
+        assert_eq!(preview.updated_refs.len(), 1);
+        assert_eq!(preview.updated_refs[0].old_oid, head_before.to_string());
+
+        // Nothing was written: refs, backups and objects are untouched
+        assert_eq!(repo.head().unwrap().target().unwrap(), head_before);
+        assert!(list_backup_entries(&repo).unwrap().is_empty());
+        assert!(repo
+            .find_commit(Oid::from_str(&preview.new_oid).unwrap())
+            .is_err());
+
+        // The real rewrite produces exactly the previewed OIDs
+        let real = rewrite_commits(&repo, &edit(), &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert!(!real.dry_run);
+        assert_eq!(real.new_oid, preview.new_oid);
+        assert_eq!(
+            real.updated_refs[0].new_oid,
+            preview.updated_refs[0].new_oid
+        );
+    }
+
+    #[test]
+    fn test_rewrite_reports_lost_signatures() {
+        let (_dir, repo) = create_test_repo(2);
+        let root_oid = repo.head().unwrap().target().unwrap();
+        let root_oid = repo.find_commit(root_oid).unwrap().parent_id(0).unwrap();
+        let signed_oid = add_signed_commit(&repo);
+
+        let result = rewrite_commit(
+            &repo,
+            root_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.lost_signatures, vec![signed_oid.to_string()]);
+        assert!(result.resigned_commits.is_empty());
+        let new_tip = Oid::from_str(&result.updated_refs[0].new_oid).unwrap();
+        assert!(repo.extract_signature(&new_tip, None).is_err());
+    }
+
+    #[test]
+    fn test_rewrite_resigns_with_custom_signer() {
+        let (_dir, repo) = create_test_repo(2);
+        let root_oid = repo.head().unwrap().target().unwrap();
+        let root_oid = repo.find_commit(root_oid).unwrap().parent_id(0).unwrap();
+        let signed_oid = add_signed_commit(&repo);
+
+        let options = RewriteOptions {
+            signer: Some(Arc::new(FakeSigner)),
+            ..Default::default()
+        };
+        // Without signing, a preview cannot know the final OIDs
+        let preview_options = RewriteOptions {
+            dry_run: true,
+            ..options.clone()
+        };
+        let preview = rewrite_commit(
+            &repo,
+            root_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &preview_options,
+            &|_, _| {},
+        )
+        .unwrap();
+        assert!(preview.approximate_oids);
+
+        let result = rewrite_commit(
+            &repo,
+            root_oid,
+            CommitEdit {
+                message: Some("Changed root".to_string()),
+                ..Default::default()
+            },
+            &options,
+            &|_, _| {},
+        )
+        .unwrap();
+
+        assert_eq!(result.resigned_commits, vec![signed_oid.to_string()]);
+        assert!(result.lost_signatures.is_empty());
+        assert!(!result.approximate_oids);
+        assert_ne!(
+            result.updated_refs[0].new_oid,
+            preview.updated_refs[0].new_oid
+        );
+
+        // Only the originally signed commit gets a signature
+        let new_tip = Oid::from_str(&result.updated_refs[0].new_oid).unwrap();
+        let (signature, _) = repo.extract_signature(&new_tip, None).unwrap();
+        assert!(signature
+            .as_str()
+            .unwrap()
+            .starts_with("-----BEGIN FAKE SIGNATURE-----"));
+        let new_root = Oid::from_str(&result.new_oid).unwrap();
+        assert!(repo.extract_signature(&new_root, None).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::Error;
+use git2::{Oid, ReferenceType, Repository};
+use serde::Serialize;
+
+/// Refs that already contain a commit, so rewriting it would need a force push
+/// (or a moved tag) to take effect anywhere else.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PublishedRefs {
+    pub oid: String,
+    /// Remote-tracking refs (`refs/remotes/*`) whose history contains the commit.
+    pub remote_refs: Vec<String>,
+    /// Tags (`refs/tags/*`) whose commit is the commit or one of its descendants.
+    pub tags: Vec<String>,
+}
+
+impl PublishedRefs {
+    pub fn is_published(&self) -> bool {
+        !self.remote_refs.is_empty() || !self.tags.is_empty()
+    }
+}
+
+/// The commits remote-tracking refs and tags point to, checked with
+/// `graph_descendant_of`. Collected once per rewrite.
+pub(crate) struct PublishedCheck {
+    targets: Vec<(String, Oid)>,
+}
+
+impl PublishedCheck {
+    /// Collect every remote-tracking ref and tag except those in `rewritten`,
+    /// which the rewrite moves itself.
+    pub(crate) fn new(repo: &Repository, rewritten: &[(String, Oid)]) -> Result<Self, Error> {
+        let mut targets = Vec::new();
+        for glob in ["refs/remotes/*", "refs/tags/*"] {
+            for reference in repo.references_glob(glob)? {
+                let reference = reference?;
+                // `refs/remotes/origin/HEAD` only repeats the branch it points to
+                if reference.kind() == Some(ReferenceType::Symbolic) {
+                    continue;
+                }
+                let Some(name) = reference.name() else {
+                    continue;
+                };
+                if rewritten.iter().any(|(r, _)| r == name) {
+                    continue;
+                }
+                // Tags of trees or blobs cannot contain a commit
+                if let Ok(commit) = reference.peel_to_commit() {
+                    targets.push((name.to_string(), commit.id()));
+                }
+            }
+        }
+        Ok(PublishedCheck { targets })
+    }
+
+    /// Names of the collected refs whose history contains `oid`.
+    pub(crate) fn containing(&self, repo: &Repository, oid: Oid) -> Result<Vec<String>, Error> {
+        let mut refs = Vec::new();
+        for (name, target) in &self.targets {
+            if *target == oid || repo.graph_descendant_of(*target, oid)? {
+                refs.push(name.clone());
+            }
+        }
+        Ok(refs)
+    }
+}
+
+/// Report which remote-tracking refs and tags already contain `oid`.
+/// Rewrites refuse to change such commits unless `allow_published` is set.
+pub fn published_refs(repo: &Repository, oid: Oid) -> Result<PublishedRefs, Error> {
+    repo.find_commit(oid)?;
+    let (tags, remote_refs) = PublishedCheck::new(repo, &[])?
+        .containing(repo, oid)?
+        .into_iter()
+        .partition(|name| name.starts_with("refs/tags/"));
+    Ok(PublishedRefs {
+        oid: oid.to_string(),
+        remote_refs,
+        tags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewrite::{rewrite_commits, CommitEdit, RewriteOptions};
+    use crate::test_support::{commit_files, create_test_repo};
+
+    /// A repo whose first two commits were pushed to a local bare "origin".
+    fn pushed_repo() -> (tempfile::TempDir, tempfile::TempDir, Repository) {
+        let (dir, repo) = create_test_repo(2);
+        let remote_dir = tempfile::TempDir::new().unwrap();
+        Repository::init_bare(remote_dir.path()).unwrap();
+        {
+            let mut remote = repo
+                .remote("origin", remote_dir.path().to_str().unwrap())
+                .unwrap();
+            remote
+                .push(&["refs/heads/main:refs/heads/main"], None)
+                .unwrap();
+            remote
+                .fetch(&["refs/heads/main:refs/remotes/origin/main"], None, None)
+                .unwrap();
+        }
+        commit_files(&repo, &[("local.txt", Some(b"local"))], "Local commit");
+        (dir, remote_dir, repo)
+    }
+
+    fn reword(oid: Oid) -> Vec<(Oid, CommitEdit)> {
+        vec![(
+            oid,
+            CommitEdit {
+                message: Some("Reworded".to_string()),
+                ..Default::default()
+            },
+        )]
+    }
+
+    #[test]
+    fn test_published_refs_finds_remote_branches_and_tags() {
+        let (_dir, _remote, repo) = pushed_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let pushed = head.parent_id(0).unwrap();
+        repo.tag_lightweight("v1", head.as_object(), false).unwrap();
+
+        let report = published_refs(&repo, pushed).unwrap();
+        assert_eq!(report.remote_refs, ["refs/remotes/origin/main"]);
+        assert_eq!(report.tags, ["refs/tags/v1"]);
+        assert!(report.is_published());
+
+        let report = published_refs(&repo, head.id()).unwrap();
+        assert!(report.remote_refs.is_empty());
+        assert_eq!(report.tags, ["refs/tags/v1"]);
+    }
+
+    #[test]
+    fn test_rewrite_refuses_pushed_commits_without_override() {
+        let (_dir, _remote, repo) = pushed_repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let pushed = head.parent_id(0).unwrap();
+
+        let err = rewrite_commits(
+            &repo,
+            &reword(pushed),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), "already_published");
+        assert_eq!(err.oid(), Some(pushed.to_string().as_str()));
+        assert_eq!(repo.head().unwrap().target(), Some(head.id()));
+
+        // The unpushed commit can be rewritten freely
+        rewrite_commits(
+            &repo,
+            &reword(head.id()),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let options = RewriteOptions {
+            allow_published: true,
+            ..Default::default()
+        };
+        rewrite_commits(&repo, &reword(pushed), &options, &|_, _| {}).unwrap();
+        assert_ne!(repo.head().unwrap().target(), Some(head.id()));
+    }
+}
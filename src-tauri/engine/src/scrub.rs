@@ -0,0 +1,438 @@
+use crate::error::Error;
+use crate::rewrite::{execute_rewrite, plan_rewrite, CommitAction, RewriteOptions, RewriteResult};
+use git2::{ObjectType, Oid, Repository, Tree};
+use regex::bytes::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// Text written in place of every match when no replacement is given.
+pub const DEFAULT_SCRUB_REPLACEMENT: &str = "***REMOVED***";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ScrubOptions {
+    /// Strings to replace exactly as written.
+    pub literals: Vec<String>,
+    /// Regular expressions to replace (Rust `regex` syntax).
+    pub patterns: Vec<String>,
+    /// Defaults to `DEFAULT_SCRUB_REPLACEMENT`. For `patterns` it may refer to
+    /// capture groups as `$1` or `${name}`; for `literals` it is used as is.
+    pub replacement: Option<String>,
+    /// Also rewrite blobs git considers binary; they are left alone by default.
+    pub include_binary: bool,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ScrubbedCommit {
+    /// The original commit.
+    pub oid: String,
+    /// Files whose contents were scrubbed in this commit's tree, sorted.
+    pub paths: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct ScrubResult {
+    #[serde(flatten)]
+    pub rewrite: RewriteResult,
+    /// Distinct blobs that contained a match.
+    pub blobs_scrubbed: usize,
+    /// Every commit whose tree contained a match, oldest first.
+    pub matched_commits: Vec<ScrubbedCommit>,
+    /// Every scrubbed file path across history, sorted.
+    pub matched_paths: Vec<String>,
+}
+
+/// Rewrites blob contents. Blobs and trees are cached by OID, so each unique
+/// blob is scanned once however many commits and paths share it. A cached
+/// tree remembers which paths below it changed.
+struct BlobScrubber<'r> {
+    repo: &'r Repository,
+    rules: Vec<ScrubRule>,
+    replacement: Vec<u8>,
+    include_binary: bool,
+    /// Only hash new blobs and trees instead of writing them.
+    dry_run: bool,
+    blobs: HashMap<Oid, Option<Oid>>,
+    trees: HashMap<Oid, (Oid, Rc<Vec<String>>)>,
+}
+
+impl<'r> BlobScrubber<'r> {
+    fn scrub_blob(&mut self, oid: Oid) -> Result<Option<Oid>, Error> {
+        if let Some(scrubbed) = self.blobs.get(&oid) {
+            return Ok(*scrubbed);
+        }
+        let blob = self.repo.find_blob(oid)?;
+        let scrubbed = if blob.is_binary() && !self.include_binary {
+            None
+        } else {
+            match self.scrub_content(blob.content()) {
+                Some(content) if self.dry_run => {
+                    Some(Oid::hash_object(ObjectType::Blob, &content)?)
+                }
+                Some(content) => Some(self.repo.blob(&content)?),
+                None => None,
+            }
+        };
+        self.blobs.insert(oid, scrubbed);
+        Ok(scrubbed)
+    }
+
+    /// Apply the rules in order, each to the output of the previous one.
+    /// Returns `None` if none of them matches.
+    fn scrub_content(&self, content: &[u8]) -> Option<Vec<u8>> {
+        let mut current: Option<Vec<u8>> = None;
+        for rule in &self.rules {
+            let input = current.as_deref().unwrap_or(content);
+            let replaced = match rule {
+                ScrubRule::Literal(regex) => regex.replace_all(input, NoExpand(&self.replacement)),
+                ScrubRule::Pattern(regex) => regex.replace_all(input, self.replacement.as_slice()),
+            };
+            if let Cow::Owned(replaced) = replaced {
+                current = Some(replaced);
+            }
+        }
+        current
+    }
+
+    /// Scrub `tree`, returning its new OID and the changed paths relative to it.
+    fn scrub_tree(&mut self, tree: &Tree) -> Result<(Oid, Rc<Vec<String>>), Error> {
+        if let Some((scrubbed, paths)) = self.trees.get(&tree.id()) {
+            return Ok((*scrubbed, paths.clone()));
+        }
+
+        let repo = self.repo;
+        let mut replaced: HashMap<Vec<u8>, Oid> = HashMap::new();
+        let mut paths = Vec::new();
+        for entry in tree.iter() {
+            let name = String::from_utf8_lossy(entry.name_bytes()).into_owned();
+            let scrubbed = match entry.kind() {
+                Some(ObjectType::Tree) => {
+                    let (scrubbed, below) = self.scrub_tree(&repo.find_tree(entry.id())?)?;
+                    paths.extend(below.iter().map(|p| format!("{}/{}", name, p)));
+                    Some(scrubbed).filter(|oid| *oid != entry.id())
+                }
+                Some(ObjectType::Blob) => {
+                    let scrubbed = self.scrub_blob(entry.id())?;
+                    if scrubbed.is_some() {
+                        paths.push(name);
+                    }
+                    scrubbed
+                }
+                // Submodule commits have no contents here
+                _ => None,
+            };
+            if let Some(scrubbed) = scrubbed {
+                replaced.insert(entry.name_bytes().to_vec(), scrubbed);
+            }
+        }
+
+        let scrubbed = if replaced.is_empty() {
+            tree.id()
+        } else {
+            self.write_tree(tree, &replaced)?
+        };
+        paths.sort();
+        let paths = Rc::new(paths);
+        self.trees.insert(tree.id(), (scrubbed, paths.clone()));
+        Ok((scrubbed, paths))
+    }
+
+    /// `tree` with the entries in `replaced` pointing at new objects. In a dry
+    /// run the tree is serialized and hashed instead of written; replacing an
+    /// entry's object keeps its name and kind, so the entry order stays valid.
+    fn write_tree(&self, tree: &Tree, replaced: &HashMap<Vec<u8>, Oid>) -> Result<Oid, Error> {
+        if !self.dry_run {
+            let mut builder = self.repo.treebuilder(Some(tree))?;
+            for entry in tree.iter() {
+                if let Some(oid) = replaced.get(entry.name_bytes()) {
+                    builder.insert(entry.name_bytes(), *oid, entry.filemode())?;
+                }
+            }
+            return Ok(builder.write()?);
+        }
+        let mut buffer = Vec::new();
+        for entry in tree.iter() {
+            let (oid, mode) = match replaced.get(entry.name_bytes()) {
+                Some(oid) => (*oid, entry.filemode()),
+                None => (entry.id(), entry.filemode_raw()),
+            };
+            buffer.extend_from_slice(format!("{:o} ", mode).as_bytes());
+            buffer.extend_from_slice(entry.name_bytes());
+            buffer.push(0);
+            buffer.extend_from_slice(oid.as_bytes());
+        }
+        Ok(Oid::hash_object(ObjectType::Tree, &buffer)?)
+    }
+}
+
+/// One literal or pattern; literals are replaced without `$` expansion.
+enum ScrubRule {
+    Literal(Regex),
+    Pattern(Regex),
+}
+
+/// Compile the literals, then the patterns, in order, reporting the first
+/// invalid pattern by index. Each is its own regex, so `$1` in the
+/// replacement refers to a group of the pattern that matched.
+fn compile_scrub_rules(scrub: &ScrubOptions) -> Result<Vec<ScrubRule>, Error> {
+    if scrub
+        .literals
+        .iter()
+        .chain(&scrub.patterns)
+        .all(|p| p.is_empty())
+    {
+        return Err(Error::invalid_input(
+            "patterns",
+            "Give at least one string or pattern to replace",
+        ));
+    }
+    let mut rules = Vec::new();
+    for literal in scrub.literals.iter().filter(|l| !l.is_empty()) {
+        let regex = Regex::new(&regex::escape(literal))
+            .map_err(|e| Error::invalid_input("literals", e.to_string()))?;
+        rules.push(ScrubRule::Literal(regex));
+    }
+    for (i, pattern) in scrub.patterns.iter().enumerate() {
+        if pattern.is_empty() {
+            continue;
+        }
+        let regex = Regex::new(pattern).map_err(|e| {
+            Error::invalid_input(
+                format!("patterns[{}]", i),
+                format!("Invalid pattern {}: {}", i + 1, e),
+            )
+        })?;
+        rules.push(ScrubRule::Pattern(regex));
+    }
+    Ok(rules)
+}
+
+/// Replace every occurrence of `scrub.literals` and `scrub.patterns` in every
+/// blob of the selected history, like `git filter-repo --replace-text`.
+/// Binary blobs (as detected by git) are skipped unless `include_binary` is
+/// set. Trees and blobs that contain no match keep their OIDs, and commits
+/// whose tree does not change are only re-parented. The original blobs stay in
+/// the object database until the backup entries are deleted (see
+/// `prune_backups`) and git garbage-collects them.
+pub fn scrub_blobs(
+    repo: &Repository,
+    scrub: &ScrubOptions,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<ScrubResult, Error> {
+    let rules = compile_scrub_rules(scrub)?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let mut scrubber = BlobScrubber {
+        repo,
+        rules,
+        replacement: scrub
+            .replacement
+            .as_deref()
+            .unwrap_or(DEFAULT_SCRUB_REPLACEMENT)
+            .as_bytes()
+            .to_vec(),
+        include_binary: scrub.include_binary,
+        dry_run: options.dry_run,
+        blobs: HashMap::new(),
+        trees: HashMap::new(),
+    };
+    let mut matched_commits = Vec::new();
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            let (tree, paths) = scrubber.scrub_tree(&commit.tree()?)?;
+            if tree == commit.tree_id() {
+                return Ok(CommitAction::Keep);
+            }
+            matched_commits.push(ScrubbedCommit {
+                oid: commit.id().to_string(),
+                paths: paths.to_vec(),
+            });
+            Ok(CommitAction::Edit {
+                edit: Default::default(),
+                tree: Some(tree),
+            })
+        },
+        "scrubbed text from history",
+        options,
+        on_progress,
+    )?;
+
+    if matched_commits.is_empty() {
+        return Err(Error::nothing_to_rewrite(
+            "No file in history contains the given text",
+        ));
+    }
+    let matched_paths: BTreeSet<String> = matched_commits
+        .iter()
+        .flat_map(|c| c.paths.iter().cloned())
+        .collect();
+    Ok(ScrubResult {
+        rewrite: outcome.into_result(None, options.dry_run)?,
+        blobs_scrubbed: scrubber.blobs.values().filter(|b| b.is_some()).count(),
+        matched_commits,
+        matched_paths: matched_paths.into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{commit_files, create_test_repo};
+    use std::path::Path;
+
+    fn file_at(repo: &Repository, path: &str) -> Vec<u8> {
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let entry = tree.get_path(Path::new(path)).unwrap();
+        repo.find_blob(entry.id()).unwrap().content().to_vec()
+    }
+
+    #[test]
+    fn test_scrub_literal_across_history() {
+        let (dir, repo) = create_test_repo(1);
+        std::fs::create_dir(dir.path().join("config")).unwrap();
+        let leak = commit_files(
+            &repo,
+            &[("config/app.toml", Some(b"token = \"sk-live-1234\"\n"))],
+            "Add config",
+        );
+        let unrelated = commit_files(&repo, &[("readme.md", Some(b"# App\n"))], "Add readme");
+        let copy = commit_files(
+            &repo,
+            &[("notes.txt", Some(b"old key sk-live-1234\n"))],
+            "Notes",
+        );
+
+        let scrub = ScrubOptions {
+            literals: vec!["sk-live-1234".to_string()],
+            ..Default::default()
+        };
+        let result = scrub_blobs(&repo, &scrub, &RewriteOptions::default(), &|_, _| {}).unwrap();
+
+        assert_eq!(result.blobs_scrubbed, 2);
+        let matched: Vec<(&str, &[String])> = result
+            .matched_commits
+            .iter()
+            .map(|c| (c.oid.as_str(), c.paths.as_slice()))
+            .collect();
+        let leak_oid = leak.to_string();
+        let unrelated_oid = unrelated.to_string();
+        let copy_oid = copy.to_string();
+        assert_eq!(
+            matched,
+            [
+                (leak_oid.as_str(), &["config/app.toml".to_string()][..]),
+                (unrelated_oid.as_str(), &["config/app.toml".to_string()][..]),
+                (
+                    copy_oid.as_str(),
+                    &["config/app.toml".to_string(), "notes.txt".to_string()][..]
+                ),
+            ]
+        );
+        assert_eq!(result.matched_paths, ["config/app.toml", "notes.txt"]);
+        assert_eq!(
+            file_at(&repo, "config/app.toml"),
+            b"token = \"***REMOVED***\"\n"
+        );
+        assert_eq!(file_at(&repo, "notes.txt"), b"old key ***REMOVED***\n");
+        assert_eq!(file_at(&repo, "file_0.txt"), b"content 0");
+    }
+
+    #[test]
+    fn test_scrub_regex_skips_binary_unless_included() {
+        let (_dir, repo) = create_test_repo(1);
+        commit_files(
+            &repo,
+            &[
+                ("keys.env", Some(b"AWS_KEY=AKIA0123456789ABCDEF\n")),
+                ("dump.bin", Some(b"\0\0AKIA0123456789ABCDEF\0")),
+            ],
+            "Add keys",
+        );
+        let mut scrub = ScrubOptions {
+            patterns: vec![r"AKIA[0-9A-Z]{16}".to_string()],
+            replacement: Some("<aws-key>".to_string()),
+            ..Default::default()
+        };
+
+        let result = scrub_blobs(&repo, &scrub, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.matched_paths, ["keys.env"]);
+        assert_eq!(file_at(&repo, "keys.env"), b"AWS_KEY=<aws-key>\n");
+        assert_eq!(file_at(&repo, "dump.bin"), b"\0\0AKIA0123456789ABCDEF\0");
+
+        scrub.include_binary = true;
+        let result = scrub_blobs(&repo, &scrub, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.matched_paths, ["dump.bin"]);
+        assert_eq!(file_at(&repo, "dump.bin"), b"\0\0<aws-key>\0");
+    }
+
+    #[test]
+    fn test_scrub_rules_apply_in_order_with_their_own_groups() {
+        let (_dir, repo) = create_test_repo(1);
+        commit_files(
+            &repo,
+            &[("app.env", Some(b"user=alice key=k-42 price=$5\n"))],
+            "Add env",
+        );
+        let scrub = ScrubOptions {
+            literals: vec!["price=$5".to_string()],
+            patterns: vec![r"user=(\w+)".to_string(), r"key=k-(\d+)".to_string()],
+            replacement: Some("<$1>".to_string()),
+            ..Default::default()
+        };
+
+        scrub_blobs(&repo, &scrub, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(file_at(&repo, "app.env"), b"<alice> <42> <$1>\n");
+    }
+
+    #[test]
+    fn test_scrub_validation_and_dry_run() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().target();
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let invalid = ScrubOptions {
+            literals: vec!["content".to_string()],
+            patterns: vec!["ok".to_string(), "(unclosed".to_string()],
+            ..Default::default()
+        };
+        let err = scrub_blobs(&repo, &invalid, &dry, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.field(), Some("patterns[1]"));
+
+        let missing = ScrubOptions {
+            literals: vec!["not in any file".to_string()],
+            ..Default::default()
+        };
+        let err = scrub_blobs(&repo, &missing, &dry, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "nothing_to_rewrite");
+
+        let scrub = ScrubOptions {
+            literals: vec!["content 0".to_string()],
+            ..Default::default()
+        };
+        let preview = scrub_blobs(&repo, &scrub, &dry, &|_, _| {}).unwrap();
+        assert_eq!(preview.matched_commits.len(), 2);
+        assert_eq!(repo.head().unwrap().target(), head);
+
+        // Nothing was written, yet the real run produces the previewed OIDs
+        let blob = Oid::hash_object(ObjectType::Blob, b"***REMOVED***").unwrap();
+        assert!(!repo.odb().unwrap().exists(blob));
+        let real = scrub_blobs(&repo, &scrub, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(
+            real.rewrite.updated_refs[0].new_oid,
+            preview.rewrite.updated_refs[0].new_oid
+        );
+        assert!(repo.odb().unwrap().exists(blob));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::error::Error;
+use git2::{Config, Repository};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Produces a detached signature for a serialized commit, which is stored in
+/// the commit's `gpgsig` header. Failures are `SigningFailed`; the rewrite
+/// fills in the commit being signed.
+pub trait CommitSigner: Send + Sync {
+    fn sign(&self, commit_buffer: &str) -> Result<String, Error>;
+}
+
+/// Signs with `<program> --status-fd=2 -bsa [-u <key>]`, like `git commit -S`
+/// with `gpg.format` set to `openpgp` or `x509`.
+pub struct GpgSigner {
+    pub program: String,
+    pub key: Option<String>,
+}
+
+/// Signs with `<program> -Y sign -n git -f <key_file>`, like `git commit -S`
+/// with `gpg.format=ssh`.
+pub struct SshSigner {
+    pub program: String,
+    pub key_file: PathBuf,
+}
+
+fn signing_failed(message: String) -> Error {
+    Error::SigningFailed {
+        oid: String::new(),
+        message,
+    }
+}
+
+fn run_signing_program(program: &str, args: &[&str], commit_buffer: &str) -> Result<String, Error> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| signing_failed(format!("Failed to run {}: {}", program, e)))?;
+    child
+        .stdin
+        .take()
+        .ok_or_else(|| signing_failed("Failed to open signer stdin".to_string()))?
+        .write_all(commit_buffer.as_bytes())
+        .map_err(|e| signing_failed(format!("Failed to write to {}: {}", program, e)))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|e| signing_failed(format!("Failed to run {}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(signing_failed(format!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| signing_failed(format!("{} produced a non-UTF-8 signature", program)))
+}
+
+impl CommitSigner for GpgSigner {
+    fn sign(&self, commit_buffer: &str) -> Result<String, Error> {
+        let mut args = vec!["--status-fd=2", "-bsa"];
+        if let Some(key) = &self.key {
+            args.extend(["-u", key.as_str()]);
+        }
+        run_signing_program(&self.program, &args, commit_buffer)
+    }
+}
+
+impl CommitSigner for SshSigner {
+    fn sign(&self, commit_buffer: &str) -> Result<String, Error> {
+        let key_file = self.key_file.to_string_lossy();
+        run_signing_program(
+            &self.program,
+            &["-Y", "sign", "-n", "git", "-f", &key_file],
+            commit_buffer,
+        )
+    }
+}
+
+/// What to do with the signature of a signed commit that has to be rewritten.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SigningMode {
+    /// Drop the signature (the rewritten commit is unsigned).
+    #[default]
+    Strip,
+    /// Re-sign it the way `git commit -S` would, with the signing program and
+    /// key from the repository's git configuration.
+    Sign,
+}
+
+fn config_string(config: &Config, name: &str) -> Option<String> {
+    config
+        .get_string(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// The signer `git commit -S` would use in `repo`: `gpg.format` picks the
+/// backend, `gpg.<format>.program` (or `gpg.program` for OpenPGP) the
+/// program and `user.signingkey` the key, which SSH signing requires.
+pub(crate) fn configured_signer(repo: &Repository) -> Result<Arc<dyn CommitSigner>, Error> {
+    let config = repo.config()?.snapshot()?;
+    let format = config_string(&config, "gpg.format").unwrap_or_else(|| "openpgp".to_string());
+    let program = |default: &str| {
+        config_string(&config, &format!("gpg.{}.program", format))
+            .or_else(|| {
+                (format == "openpgp")
+                    .then(|| config_string(&config, "gpg.program"))
+                    .flatten()
+            })
+            .unwrap_or_else(|| default.to_string())
+    };
+    match format.as_str() {
+        "openpgp" | "x509" => Ok(Arc::new(GpgSigner {
+            program: program(if format == "x509" { "gpgsm" } else { "gpg" }),
+            key: config_string(&config, "user.signingkey"),
+        })),
+        "ssh" => {
+            let key_file = config.get_path("user.signingkey").map_err(|_| {
+                Error::invalid_input("signing", "SSH signing needs user.signingkey to be set")
+            })?;
+            Ok(Arc::new(SshSigner {
+                program: program("ssh-keygen"),
+                key_file,
+            }))
+        }
+        other => Err(Error::invalid_input(
+            "signing",
+            format!("Unsupported gpg.format '{}'", other),
+        )),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::rewrite::{rewrite_commits, CommitEdit, RewriteOptions};
+    use crate::test_support::{add_signed_commit, create_test_repo};
+    use git2::Oid;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Write an executable shell script into the repository's `.git` directory.
+    fn script(repo: &Repository, name: &str, body: &str) -> String {
+        let path = repo.path().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Reword the signed HEAD commit with `signing: Sign`.
+    fn reword_signed(repo: &Repository) -> Result<Oid, Error> {
+        let signed = add_signed_commit(repo);
+        let edits = vec![(
+            signed,
+            CommitEdit {
+                message: Some("Reworded".to_string()),
+                ..Default::default()
+            },
+        )];
+        let options = RewriteOptions {
+            signing: SigningMode::Sign,
+            ..Default::default()
+        };
+        let result = rewrite_commits(repo, &edits, &options, &|_, _| {})?;
+        Ok(Oid::from_str(&result.new_oid).unwrap())
+    }
+
+    #[test]
+    fn test_gpg_signer_from_config() {
+        let (_dir, repo) = create_test_repo(1);
+        let program = script(
+            &repo,
+            "fake-gpg",
+            r#"[ "$*" = "--status-fd=2 -bsa -u ABCD" ] || exit 1
+cat > /dev/null
+printf -- '-----BEGIN PGP SIGNATURE-----\nfake\n-----END PGP SIGNATURE-----\n'"#,
+        );
+        let mut config = repo.config().unwrap();
+        config.set_str("gpg.program", &program).unwrap();
+        config.set_str("user.signingkey", "ABCD").unwrap();
+
+        let new_oid = reword_signed(&repo).unwrap();
+        let (signature, _) = repo.extract_signature(&new_oid, None).unwrap();
+        assert!(signature.as_str().unwrap().contains("\nfake\n"));
+    }
+
+    #[test]
+    fn test_ssh_signer_from_config() {
+        let (_dir, repo) = create_test_repo(1);
+        let program = script(
+            &repo,
+            "fake-ssh-keygen",
+            r#"[ "$1 $2 $3 $4 $5" = "-Y sign -n git -f" ] || exit 1
+cat > /dev/null
+printf -- '-----BEGIN SSH SIGNATURE-----\n%s\n-----END SSH SIGNATURE-----\n' "$6""#,
+        );
+        let mut config = repo.config().unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config.set_str("gpg.ssh.program", &program).unwrap();
+
+        // The key is required
+        let err = reword_signed(&repo).err().unwrap();
+        assert_eq!(err.field(), Some("signing"));
+
+        config
+            .set_str("user.signingkey", "/keys/id_ed25519")
+            .unwrap();
+        let new_oid = reword_signed(&repo).unwrap();
+        let (signature, _) = repo.extract_signature(&new_oid, None).unwrap();
+        assert!(signature.as_str().unwrap().contains("\n/keys/id_ed25519\n"));
+    }
+
+    #[test]
+    fn test_failing_signer_is_a_typed_error() {
+        let (_dir, repo) = create_test_repo(1);
+        let program = script(
+            &repo,
+            "broken-gpg",
+            "cat > /dev/null\necho 'no secret key' >&2\nexit 2",
+        );
+        repo.config()
+            .unwrap()
+            .set_str("gpg.program", &program)
+            .unwrap();
+
+        let err = reword_signed(&repo).err().unwrap();
+        let Error::SigningFailed { oid, message } = &err else {
+            panic!("expected a signing failure, got {:?}", err);
+        };
+        assert_eq!(oid, &repo.head().unwrap().target().unwrap().to_string());
+        assert!(message.ends_with("failed: no secret key"));
+    }
+}
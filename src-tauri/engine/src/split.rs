@@ -0,0 +1,290 @@
+use crate::error::Error;
+use crate::rewrite::{
+    execute_rewrite, plan_rewrite, CommitAction, CommitEdit, RefSelection, RewriteOptions,
+    RewriteResult,
+};
+use git2::build::TreeUpdateBuilder;
+use git2::{Delta, Oid, Pathspec, PathspecFlags, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One commit of a split: the changes selected by `paths` and their message.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SplitGroup {
+    /// Git pathspecs such as `src/`, `*.md` or `docs/guide.md`.
+    pub paths: Vec<String>,
+    pub message: String,
+}
+
+/// Split a non-merge commit into one commit per group, oldest first. Each
+/// commit adds its group's changes to the tree of the previous one, starting
+/// from the parent's tree; a change matched by several groups belongs to the
+/// first. Changes no group matches go into a final commit that keeps the
+/// original message, so the last tree always equals the original one. Every
+/// part keeps the original author and committer, and descendants are
+/// re-parented onto the last part. `new_oid` on the result is the last part;
+/// `edited_commits` lists every part.
+pub fn split_commit(
+    repo: &Repository,
+    oid: Oid,
+    groups: &[SplitGroup],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let plan = plan_rewrite(repo, &options.refs)?;
+    if !plan.oids.contains(&oid) {
+        return Err(Error::CommitNotInHistory {
+            oid: oid.to_string(),
+            head_only: matches!(options.refs, RefSelection::Head),
+        });
+    }
+    let commit = repo.find_commit(oid)?;
+    if commit.parent_count() > 1 {
+        return Err(Error::invalid_input(
+            "oid",
+            format!("Cannot split merge commit {}", oid),
+        ));
+    }
+    if groups.is_empty() {
+        return Err(Error::invalid_input(
+            "groups",
+            "Give at least one group of paths",
+        ));
+    }
+
+    let mut pathspecs = Vec::new();
+    for (i, group) in groups.iter().enumerate() {
+        if group.paths.is_empty() {
+            return Err(Error::invalid_input(
+                format!("groups[{}].paths", i),
+                "A group needs at least one path",
+            ));
+        }
+        if group.message.trim().is_empty() {
+            return Err(Error::invalid_input(
+                format!("groups[{}].message", i),
+                "A group needs a message",
+            ));
+        }
+        pathspecs.push(
+            Pathspec::new(group.paths.iter())
+                .map_err(|e| Error::invalid_input(format!("groups[{}].paths", i), e.message()))?,
+        );
+    }
+
+    let mut tree = match commit.parent_count() {
+        0 => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+        _ => commit.parent(0)?.tree()?,
+    };
+    let diff = repo.diff_tree_to_tree(Some(&tree), Some(&commit.tree()?), None)?;
+
+    // The last bucket collects the changes no group matches
+    let mut buckets: Vec<Vec<git2::DiffDelta>> = (0..=groups.len()).map(|_| Vec::new()).collect();
+    for delta in diff.deltas() {
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let group = pathspecs
+            .iter()
+            .position(|spec| spec.matches_path(path, PathspecFlags::DEFAULT))
+            .unwrap_or(groups.len());
+        buckets[group].push(delta);
+    }
+    if let Some(empty) = buckets[..groups.len()].iter().position(|b| b.is_empty()) {
+        return Err(Error::invalid_input(
+            format!("groups[{}].paths", empty),
+            format!("No change in commit {} matches group {}", oid, empty + 1),
+        ));
+    }
+
+    let mut parts = Vec::new();
+    for (i, bucket) in buckets.iter().enumerate() {
+        if bucket.is_empty() {
+            continue;
+        }
+        let mut update = TreeUpdateBuilder::new();
+        // A type change shows up as a deletion plus an addition of the same path
+        let upserted: HashSet<&std::path::Path> = bucket
+            .iter()
+            .filter(|d| d.status() != Delta::Deleted)
+            .filter_map(|d| d.new_file().path())
+            .collect();
+        for delta in bucket {
+            match (
+                delta.status(),
+                delta.old_file().path(),
+                delta.new_file().path(),
+            ) {
+                (Delta::Deleted, Some(path), _) if !upserted.contains(path) => {
+                    update.remove(path);
+                }
+                (Delta::Deleted, _, _) => {}
+                (_, _, Some(path)) => {
+                    update.upsert(path, delta.new_file().id(), delta.new_file().mode());
+                }
+                _ => {}
+            }
+        }
+        let tree_oid = update.create_updated(repo, &tree)?;
+        tree = repo.find_tree(tree_oid)?;
+
+        let message = match groups.get(i) {
+            Some(group) => group.message.clone(),
+            None => commit.message().unwrap_or("").to_string(),
+        };
+        let edit = CommitEdit {
+            message: Some(message),
+            ..Default::default()
+        };
+        parts.push((edit, tree_oid));
+    }
+    if tree.id() != commit.tree_id() {
+        return Err(Error::Internal(format!(
+            "Split parts of {} do not add up to its tree",
+            oid
+        )));
+    }
+    if parts.len() < 2 {
+        return Err(Error::invalid_input(
+            "groups",
+            "The first group already covers every change",
+        ));
+    }
+
+    let description = format!(
+        "split commit {} into {} commits",
+        &oid.to_string()[..8],
+        parts.len()
+    );
+    let mut split = Some(parts);
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |c| {
+            let parts = if c.id() == oid { split.take() } else { None };
+            Ok(match parts {
+                Some(parts) => CommitAction::Split(parts),
+                None => CommitAction::Keep,
+            })
+        },
+        &description,
+        options,
+        on_progress,
+    )?;
+    outcome.into_result(Some(oid), options.dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::list_commits;
+    use crate::test_support::{commit_files, create_test_repo};
+
+    fn group(paths: &[&str], message: &str) -> SplitGroup {
+        SplitGroup {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            message: message.to_string(),
+        }
+    }
+
+    fn wip_repo() -> (tempfile::TempDir, Repository, Oid) {
+        let (dir, repo) = create_test_repo(1);
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        let wip = commit_files(
+            &repo,
+            &[
+                ("a.txt", Some(b"a\n")),
+                ("b.txt", Some(b"b\n")),
+                ("c.txt", Some(b"c\n")),
+                ("docs/guide.md", Some(b"# Guide\n")),
+                ("file_0.txt", None),
+            ],
+            "WIP",
+        );
+        commit_files(&repo, &[("d.txt", Some(b"d\n"))], "After");
+        (dir, repo, wip)
+    }
+
+    #[test]
+    fn test_split_by_groups_with_remainder() {
+        let (_dir, repo, wip) = wip_repo();
+        let tip_tree = repo.head().unwrap().peel_to_tree().unwrap().id();
+        let groups = [
+            group(&["*.md"], "Add docs"),
+            group(&["a.txt", "b.txt"], "Add a and b"),
+        ];
+
+        let result =
+            split_commit(&repo, wip, &groups, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.edited_commits.len(), 3);
+
+        let messages: Vec<String> = list_commits(&repo, 0, 10)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.short_message)
+            .collect();
+        assert_eq!(
+            messages,
+            ["After", "WIP", "Add a and b", "Add docs", "Commit 0"]
+        );
+        assert_eq!(repo.head().unwrap().peel_to_tree().unwrap().id(), tip_tree);
+
+        let docs = repo
+            .find_commit(Oid::from_str(&result.edited_commits[0].new_oid).unwrap())
+            .unwrap();
+        let docs_tree = docs.tree().unwrap();
+        assert!(docs_tree
+            .get_path(std::path::Path::new("docs/guide.md"))
+            .is_ok());
+        assert!(docs_tree.get_path(std::path::Path::new("a.txt")).is_err());
+        assert!(docs_tree
+            .get_path(std::path::Path::new("file_0.txt"))
+            .is_ok());
+
+        let last = repo
+            .find_commit(Oid::from_str(&result.new_oid).unwrap())
+            .unwrap();
+        assert_eq!(last.tree_id(), repo.find_commit(wip).unwrap().tree_id());
+        assert_eq!(last.author().name(), Some("Test User"));
+    }
+
+    #[test]
+    fn test_split_dry_run_and_validation() {
+        let (_dir, repo, wip) = wip_repo();
+        let groups = [
+            group(&["docs/"], "Docs"),
+            group(&["a.txt", "b.txt", "c.txt", "file_0.txt"], "Rest"),
+        ];
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let head = repo.head().unwrap().target().unwrap();
+
+        let preview = split_commit(&repo, wip, &groups, &dry, &|_, _| {}).unwrap();
+        assert_eq!(repo.head().unwrap().target(), Some(head));
+        let real =
+            split_commit(&repo, wip, &groups, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(
+            preview.updated_refs[0].new_oid,
+            real.updated_refs[0].new_oid
+        );
+
+        let tip = repo.head().unwrap().target().unwrap();
+        let unmatched = split_commit(
+            &repo,
+            tip,
+            &[group(&["nope/"], "Nothing")],
+            &dry,
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(unmatched.field(), Some("groups[0].paths"));
+        let everything = split_commit(&repo, tip, &[group(&["*"], "All")], &dry, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(everything.field(), Some("groups"));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::error::Error;
+use crate::rewrite::{
+    execute_rewrite, linear_run, plan_rewrite, CommitAction, CommitEdit, RewriteOptions,
+    RewriteResult,
+};
+use git2::{Oid, Repository};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// How the messages of squashed commits are combined.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SquashMode {
+    /// Combine every message through `SquashOptions::message_template`.
+    #[default]
+    Squash,
+    /// Keep only the first (oldest) commit's message.
+    Fixup,
+}
+
+/// Message used when no template is given: every message, separated by a blank line.
+pub const DEFAULT_SQUASH_TEMPLATE: &str = "{messages}";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct SquashOptions {
+    pub mode: SquashMode,
+    /// Template for the combined message in `Squash` mode. Placeholders:
+    /// `{messages}` (all messages, blank-line separated), `{first}` (the oldest
+    /// message), `{subjects}` (one `- subject` line per commit) and `{count}`.
+    pub message_template: Option<String>,
+    /// Use this message as-is instead of combining; overrides `mode`.
+    pub message: Option<String>,
+    /// OID of the squashed commit whose author is kept; defaults to the oldest.
+    pub author_oid: Option<String>,
+}
+
+/// Fill a squash message template with the given commit messages (oldest first).
+pub fn render_squash_message(template: &str, messages: &[&str]) -> String {
+    let trimmed: Vec<&str> = messages.iter().map(|m| m.trim()).collect();
+    let subjects: Vec<String> = trimmed
+        .iter()
+        .map(|m| format!("- {}", m.lines().next().unwrap_or("")))
+        .collect();
+    // One pass, so placeholders inside the messages themselves stay as they are
+    static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+    let placeholder =
+        PLACEHOLDER.get_or_init(|| Regex::new(r"\{(messages|first|subjects|count)\}").unwrap());
+    let rendered = placeholder.replace_all(template, |caps: &Captures| match &caps[1] {
+        "messages" => trimmed.join("\n\n"),
+        "first" => trimmed.first().copied().unwrap_or("").to_string(),
+        "subjects" => subjects.join("\n"),
+        _ => messages.len().to_string(),
+    });
+    format!("{}\n", rendered.trim_end())
+}
+
+/// Squash a run of consecutive non-merge commits into one. The result has
+/// the last commit's tree and committer, the author of `author_oid` (or the
+/// first commit) and a message built according to `squash`. Descendants are
+/// remapped onto the new commit in the same walk `rewrite_commits` uses.
+/// `new_oid` on the result is the squashed commit; `old_oid` is the last of
+/// the run and the other commits of the run are listed in `dropped_commits`.
+pub fn squash_commits(
+    repo: &Repository,
+    oids: &[Oid],
+    squash: &SquashOptions,
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    let plan = plan_rewrite(repo, &options.refs)?;
+    let run = linear_run(repo, oids, &plan, &options.refs, "squash")?;
+
+    let commits: Vec<git2::Commit> = run
+        .iter()
+        .map(|oid| repo.find_commit(*oid))
+        .collect::<Result<_, _>>()?;
+    let author_commit = match &squash.author_oid {
+        None => &commits[0],
+        Some(author_oid) => {
+            let author_oid = crate::repo::parse_oid(author_oid)?;
+            commits
+                .iter()
+                .find(|c| c.id() == author_oid)
+                .ok_or_else(|| {
+                    Error::invalid_input(
+                        "author_oid",
+                        format!("Commit {} is not being squashed", author_oid),
+                    )
+                })?
+        }
+    };
+    let author = author_commit.author();
+
+    let messages: Vec<&str> = commits.iter().map(|c| c.message().unwrap_or("")).collect();
+    let message = match (&squash.message, squash.mode) {
+        (Some(message), _) => message.clone(),
+        (None, SquashMode::Fixup) => messages[0].to_string(),
+        (None, SquashMode::Squash) => render_squash_message(
+            squash
+                .message_template
+                .as_deref()
+                .unwrap_or(DEFAULT_SQUASH_TEMPLATE),
+            &messages,
+        ),
+    };
+
+    let last = *run.last().unwrap();
+    let edit = CommitEdit {
+        author_name: Some(author.name().unwrap_or("").to_string()),
+        author_email: Some(author.email().unwrap_or("").to_string()),
+        author_date: Some(author.when().seconds()),
+        author_offset: Some(author.when().offset_minutes()),
+        message: Some(message),
+        ..Default::default()
+    };
+    let squashed_away: HashSet<Oid> = run[..run.len() - 1].iter().copied().collect();
+
+    let verb = match squash.mode {
+        SquashMode::Squash => "squashed",
+        SquashMode::Fixup => "fixed up",
+    };
+    let description = format!(
+        "{} {} commits into {}",
+        verb,
+        run.len(),
+        &last.to_string()[..8]
+    );
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            Ok(if commit.id() == last {
+                CommitAction::Edit {
+                    edit: edit.clone(),
+                    tree: None,
+                }
+            } else if squashed_away.contains(&commit.id()) {
+                CommitAction::Drop
+            } else {
+                CommitAction::Keep
+            })
+        },
+        &description,
+        options,
+        on_progress,
+    )?;
+    outcome.into_result(Some(last), options.dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::{commit_detail, list_commits};
+    use crate::rewrite::RefSelection;
+    use crate::test_support::create_test_repo;
+
+    fn oids_newest_first(repo: &Repository) -> Vec<Oid> {
+        list_commits(repo, 0, 100)
+            .unwrap()
+            .iter()
+            .map(|c| Oid::from_str(&c.oid).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_squash_combines_run_and_remaps_descendants() {
+        let (_dir, repo) = create_test_repo(5);
+        let oids = oids_newest_first(&repo);
+        // Commits 1..=3, given in arbitrary order
+        let run = [oids[1], oids[3], oids[2]];
+        let squash = SquashOptions {
+            message_template: Some("Combined {count}\n\n{subjects}".to_string()),
+            author_oid: Some(oids[2].to_string()),
+            ..Default::default()
+        };
+
+        let result =
+            squash_commits(&repo, &run, &squash, &RewriteOptions::default(), &|_, _| {}).unwrap();
+        assert_eq!(result.old_oid, oids[1].to_string());
+        assert_eq!(
+            result.dropped_commits,
+            vec![oids[3].to_string(), oids[2].to_string()]
+        );
+
+        let after = list_commits(&repo, 0, 100).unwrap();
+        let messages: Vec<&str> = after.iter().map(|c| c.short_message.as_str()).collect();
+        assert_eq!(messages, ["Commit 4", "Combined 3", "Commit 0"]);
+
+        let squashed = commit_detail(&repo, Oid::from_str(&result.new_oid).unwrap()).unwrap();
+        assert_eq!(
+            squashed.message,
+            "Combined 3\n\n- Commit 1\n- Commit 2\n- Commit 3\n"
+        );
+        assert_eq!(squashed.parent_oids, vec![oids[4].to_string()]);
+        let squashed_tree = repo
+            .find_commit(Oid::from_str(&result.new_oid).unwrap())
+            .unwrap()
+            .tree_id();
+        assert_eq!(squashed_tree, repo.find_commit(oids[1]).unwrap().tree_id());
+        let tip = commit_detail(&repo, Oid::from_str(&after[0].oid).unwrap()).unwrap();
+        assert_eq!(tip.parent_oids, vec![result.new_oid.clone()]);
+    }
+
+    #[test]
+    fn test_fixup_keeps_first_message_and_root() {
+        let (_dir, repo) = create_test_repo(3);
+        let oids = oids_newest_first(&repo);
+        let squash = SquashOptions {
+            mode: SquashMode::Fixup,
+            ..Default::default()
+        };
+
+        let result = squash_commits(
+            &repo,
+            &[oids[2], oids[1]],
+            &squash,
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        let squashed = commit_detail(&repo, Oid::from_str(&result.new_oid).unwrap()).unwrap();
+        assert_eq!(squashed.message, "Commit 0");
+        assert!(squashed.parent_oids.is_empty());
+        assert_eq!(oids_newest_first(&repo).len(), 2);
+    }
+
+    #[test]
+    fn test_squash_dry_run_matches_real_run() {
+        let (_dir, repo) = create_test_repo(4);
+        let oids = oids_newest_first(&repo);
+        let run = [oids[3], oids[2]];
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+
+        let preview =
+            squash_commits(&repo, &run, &SquashOptions::default(), &dry, &|_, _| {}).unwrap();
+        assert_eq!(oids_newest_first(&repo), oids);
+        let real = squash_commits(
+            &repo,
+            &run,
+            &SquashOptions::default(),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(preview.new_oid, real.new_oid);
+        assert_eq!(
+            preview.updated_refs[0].new_oid,
+            real.updated_refs[0].new_oid
+        );
+    }
+
+    #[test]
+    fn test_squash_rejects_gaps_and_shared_commits() {
+        let (_dir, repo) = create_test_repo(4);
+        let oids = oids_newest_first(&repo);
+
+        let gap = squash_commits(
+            &repo,
+            &[oids[3], oids[1]],
+            &SquashOptions::default(),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(gap.field(), Some("oids"));
+
+        repo.branch("feature", &repo.find_commit(oids[2]).unwrap(), false)
+            .unwrap();
+        let options = RewriteOptions {
+            refs: RefSelection::LocalBranches,
+            ..Default::default()
+        };
+        let shared = squash_commits(
+            &repo,
+            &[oids[2], oids[1]],
+            &SquashOptions::default(),
+            &options,
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(shared.kind(), "invalid_input");
+
+        let single = squash_commits(
+            &repo,
+            &[oids[0]],
+            &SquashOptions::default(),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(single.field(), Some("oids"));
+    }
+
+    #[test]
+    fn test_render_squash_message() {
+        let message =
+            render_squash_message(DEFAULT_SQUASH_TEMPLATE, &["First\n\nbody\n", "Second\n"]);
+        assert_eq!(message, "First\n\nbody\n\nSecond\n");
+
+        // Placeholders in the messages are not expanded
+        let message = render_squash_message("{count}: {subjects}", &["Use {count} here", "Second"]);
+        assert_eq!(message, "2: - Use {count} here\n- Second\n");
+    }
+}
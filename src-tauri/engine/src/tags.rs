@@ -0,0 +1,348 @@
+use crate::error::Error;
+use git2::{ObjectType, Oid, Reference, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What happens to tags on commits that a rewrite replaces.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TagRewrite {
+    /// Tags keep pointing at the original commits, unless they are among the
+    /// refs being rewritten.
+    #[default]
+    Keep,
+    /// Move lightweight tags to the rewritten commit and recreate annotated
+    /// tags (same name, tagger and message) pointing at it, like
+    /// `git filter-repo` does.
+    Update,
+    /// Leave the tags alone and create `<name><suffix>` copies on the
+    /// rewritten commits instead.
+    Rename { suffix: String },
+}
+
+/// A tag whose commit is part of the rewrite walk.
+pub(crate) struct HistoryTag {
+    pub(crate) ref_name: String,
+    /// What the ref points to: the commit itself, or the tag object of an annotated tag.
+    pub(crate) target: Oid,
+    pub(crate) commit: Oid,
+}
+
+impl TagRewrite {
+    fn new_ref_name(&self, ref_name: &str) -> String {
+        match self {
+            TagRewrite::Rename { suffix } => format!("{}{}", ref_name, suffix),
+            _ => ref_name.to_string(),
+        }
+    }
+}
+
+/// Tags under `refs/tags/` pointing at one of `oids`, directly or through an
+/// annotated tag. Tags of tags are left out.
+pub(crate) fn tags_in_history(
+    repo: &Repository,
+    oids: &[Oid],
+    mode: &TagRewrite,
+) -> Result<Vec<HistoryTag>, Error> {
+    if *mode == TagRewrite::Keep {
+        return Ok(Vec::new());
+    }
+    let walked: HashSet<Oid> = oids.iter().copied().collect();
+    let mut tags = Vec::new();
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        let (Some(ref_name), Some(target)) = (reference.name(), reference.target()) else {
+            continue;
+        };
+        let commit = match repo.find_tag(target) {
+            Ok(tag) if tag.target_type() == Some(ObjectType::Commit) => tag.target_id(),
+            Ok(_) => continue,
+            Err(_) => target,
+        };
+        if !walked.contains(&commit) {
+            continue;
+        }
+        tags.push(HistoryTag {
+            ref_name: ref_name.to_string(),
+            target,
+            commit,
+        });
+    }
+    Ok(tags)
+}
+
+/// Ref changes (ref, old target, new target) that bring `tags` along to the
+/// rewritten commits; `new_commit` gives the replacement of a commit, or
+/// `None` if it was not rewritten. Created tags have a zero old target. With
+/// `Rename`, fails if the suffix gives a moved tag an invalid name or one that
+/// is already taken.
+/// Annotated tags get a new tag object with the original header and message;
+/// a signature in the message is dropped, since it would no longer verify.
+/// With `dry_run` their OIDs are computed without writing them.
+pub(crate) fn tag_moves(
+    repo: &Repository,
+    tags: &[HistoryTag],
+    mode: &TagRewrite,
+    new_commit: &dyn Fn(Oid) -> Option<Oid>,
+    dry_run: bool,
+) -> Result<Vec<(String, Oid, Oid)>, Error> {
+    let mut moves = Vec::new();
+    for tag in tags {
+        let Some(commit) = new_commit(tag.commit) else {
+            continue;
+        };
+        let ref_name = mode.new_ref_name(&tag.ref_name);
+        if let TagRewrite::Rename { suffix } = mode {
+            if suffix.is_empty() || !Reference::is_valid_name(&ref_name) {
+                return Err(Error::invalid_input(
+                    "tags",
+                    format!("'{}' is not a valid tag name suffix", suffix),
+                ));
+            }
+            if repo.find_reference(&ref_name).is_ok() {
+                return Err(Error::invalid_input(
+                    "tags",
+                    format!("Tag {} already exists", ref_name),
+                ));
+            }
+        }
+        let new_target = if tag.target == tag.commit {
+            commit
+        } else {
+            let name = ref_name.strip_prefix("refs/tags/").unwrap_or(&ref_name);
+            let buffer = rewritten_tag_buffer(repo, tag.target, commit, name)?;
+            if dry_run {
+                Oid::hash_object(ObjectType::Tag, &buffer)?
+            } else {
+                repo.odb()?.write(ObjectType::Tag, &buffer)?
+            }
+        };
+        let old_target = match mode {
+            TagRewrite::Rename { .. } => Oid::zero(),
+            _ => tag.target,
+        };
+        moves.push((ref_name, old_target, new_target));
+    }
+    Ok(moves)
+}
+
+/// The raw tag object `tag_oid` pointing at `commit` and named `name`, with
+/// any signature cut off the end of the message.
+fn rewritten_tag_buffer(
+    repo: &Repository,
+    tag_oid: Oid,
+    commit: Oid,
+    name: &str,
+) -> Result<Vec<u8>, Error> {
+    let odb = repo.odb()?;
+    let object = odb.read(tag_oid)?;
+    let data = object.data();
+    let split = data
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .map_or(data.len(), |pos| pos + 1);
+    let (header, message) = data.split_at(split);
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(data.len());
+    for line in header.split_inclusive(|b| *b == b'\n') {
+        if line.starts_with(b"object ") {
+            buffer.extend_from_slice(format!("object {}\n", commit).as_bytes());
+        } else if line.starts_with(b"tag ") {
+            buffer.extend_from_slice(format!("tag {}\n", name).as_bytes());
+        } else {
+            buffer.extend_from_slice(line);
+        }
+    }
+    let signature = [
+        &b"-----BEGIN PGP SIGNATURE-----"[..],
+        b"-----BEGIN SSH SIGNATURE-----",
+    ]
+    .iter()
+    .filter_map(|marker| {
+        message
+            .windows(marker.len())
+            .position(|w| w == *marker)
+            .filter(|pos| *pos == 0 || message[pos - 1] == b'\n')
+    })
+    .min();
+    buffer.extend_from_slice(&message[..signature.unwrap_or(message.len())]);
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::undo_rewrite;
+    use crate::rewrite::{rewrite_commits, CommitEdit, RewriteOptions};
+    use crate::test_support::create_test_repo;
+    use git2::Signature;
+
+    /// Reword the root commit of a three-commit repo with a lightweight tag
+    /// `v1` on the root and an annotated tag `v2` on HEAD.
+    fn reword_tagged_root(tags: TagRewrite) -> (tempfile::TempDir, Repository, Oid, Oid) {
+        let (dir, repo) = create_test_repo(3);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let root = head.parent(0).unwrap().parent(0).unwrap();
+        let tagger =
+            Signature::new("Tagger", "tagger@example.com", &git2::Time::new(1_000, 120)).unwrap();
+        repo.tag_lightweight("v1", root.as_object(), false).unwrap();
+        let v2 = repo
+            .tag("v2", head.as_object(), &tagger, "Release 2\n", false)
+            .unwrap();
+        let root_oid = root.id();
+        drop((head, root));
+
+        let edits = vec![(
+            root_oid,
+            CommitEdit {
+                message: Some("Reworded".to_string()),
+                ..Default::default()
+            },
+        )];
+        let options = RewriteOptions {
+            tags,
+            ..Default::default()
+        };
+        rewrite_commits(&repo, &edits, &options, &|_, _| {}).unwrap();
+        (dir, repo, root_oid, v2)
+    }
+
+    fn peeled(repo: &Repository, name: &str) -> Oid {
+        repo.find_reference(name)
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id()
+    }
+
+    #[test]
+    fn test_update_moves_and_recreates_tags() {
+        let (_dir, repo, old_root, old_v2) = reword_tagged_root(TagRewrite::Update);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let new_root = head.parent(0).unwrap().parent_id(0).unwrap();
+        assert_ne!(new_root, old_root);
+        assert_eq!(repo.refname_to_id("refs/tags/v1").unwrap(), new_root);
+
+        {
+            let v2 = repo
+                .find_tag(repo.refname_to_id("refs/tags/v2").unwrap())
+                .unwrap();
+            assert_ne!(v2.id(), old_v2);
+            assert_eq!(v2.target_id(), head.id());
+            assert_eq!(v2.name(), Some("v2"));
+            assert_eq!(v2.message(), Some("Release 2\n"));
+            let tagger = v2.tagger().unwrap();
+            assert_eq!(
+                (tagger.name(), tagger.when().seconds()),
+                (Some("Tagger"), 1_000)
+            );
+        }
+        drop(head);
+
+        // Undo puts both tags back
+        undo_rewrite(&repo, None).unwrap();
+        assert_eq!(repo.refname_to_id("refs/tags/v1").unwrap(), old_root);
+        assert_eq!(repo.refname_to_id("refs/tags/v2").unwrap(), old_v2);
+    }
+
+    #[test]
+    fn test_rename_keeps_old_tags() {
+        let (_dir, repo, old_root, old_v2) = reword_tagged_root(TagRewrite::Rename {
+            suffix: "-rewritten".to_string(),
+        });
+        assert_eq!(repo.refname_to_id("refs/tags/v1").unwrap(), old_root);
+        assert_eq!(repo.refname_to_id("refs/tags/v2").unwrap(), old_v2);
+        assert_eq!(
+            peeled(&repo, "refs/tags/v2-rewritten"),
+            repo.head().unwrap().target().unwrap()
+        );
+        assert_ne!(peeled(&repo, "refs/tags/v1-rewritten"), old_root);
+        let v2 = repo
+            .find_reference("refs/tags/v2-rewritten")
+            .unwrap()
+            .peel_to_tag()
+            .unwrap();
+        assert_eq!(v2.name(), Some("v2-rewritten"));
+    }
+
+    #[test]
+    fn test_rename_ignores_tags_on_untouched_commits() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let root = head.parent(0).unwrap();
+        // The root keeps its OID, so its taken `-new` name does not matter
+        repo.tag_lightweight("v1", root.as_object(), false).unwrap();
+        repo.tag_lightweight("v1-new", root.as_object(), false)
+            .unwrap();
+        let edits = vec![(
+            head.id(),
+            CommitEdit {
+                message: Some("Reworded".to_string()),
+                ..Default::default()
+            },
+        )];
+        let options = RewriteOptions {
+            tags: TagRewrite::Rename {
+                suffix: "-new".to_string(),
+            },
+            ..Default::default()
+        };
+        rewrite_commits(&repo, &edits, &options, &|_, _| {}).unwrap();
+        assert_eq!(repo.refname_to_id("refs/tags/v1-new").unwrap(), root.id());
+        assert!(repo.find_reference("refs/tags/v1-new-new").is_err());
+    }
+
+    #[test]
+    fn test_recreated_tag_drops_signature() {
+        let (_dir, repo) = create_test_repo(1);
+        let head = repo.head().unwrap().target().unwrap();
+        let raw = format!(
+            "object {}\ntype commit\ntag v1\ntagger T <t@example.com> 1000 +0000\n\nRelease\n\
+             -----BEGIN PGP SIGNATURE-----\nabc\n-----END PGP SIGNATURE-----\n",
+            head
+        );
+        let tag = repo
+            .odb()
+            .unwrap()
+            .write(ObjectType::Tag, raw.as_bytes())
+            .unwrap();
+
+        let buffer = rewritten_tag_buffer(&repo, tag, Oid::zero(), "v1-new").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!(
+                "object {}\ntype commit\ntag v1-new\ntagger T <t@example.com> 1000 +0000\n\nRelease\n",
+                Oid::zero()
+            )
+        );
+    }
+
+    #[test]
+    fn test_keep_leaves_tags_and_refuses_published_history() {
+        let (_dir, repo) = create_test_repo(2);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.tag_lightweight("v1", head.as_object(), false).unwrap();
+        let edits = vec![(
+            head.id(),
+            CommitEdit {
+                message: Some("Reworded".to_string()),
+                ..Default::default()
+            },
+        )];
+        let err = rewrite_commits(&repo, &edits, &RewriteOptions::default(), &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "already_published");
+
+        let options = RewriteOptions {
+            tags: TagRewrite::Rename {
+                suffix: "~".to_string(),
+            },
+            ..Default::default()
+        };
+        let err = rewrite_commits(&repo, &edits, &options, &|_, _| {})
+            .err()
+            .unwrap();
+        assert_eq!(err.field(), Some("tags"));
+    }
+}
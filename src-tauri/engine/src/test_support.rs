@@ -0,0 +1,111 @@
+use crate::error::Error;
+use crate::signing::CommitSigner;
+use git2::{Oid, Repository, Signature};
+use std::path::Path;
+use tempfile::TempDir;
+
+/// Create a temp repo with the given number of commits on "main".
+pub fn create_test_repo(num_commits: usize) -> (TempDir, Repository) {
+    let dir = TempDir::new().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+
+    // Configure so commits work
+    let sig = Signature::now("Test User", "test@example.com").unwrap();
+
+    {
+        let mut parent_oid: Option<Oid> = None;
+
+        for i in 0..num_commits {
+            let mut index = repo.index().unwrap();
+            let file_path = format!("file_{}.txt", i);
+            let full_path = dir.path().join(&file_path);
+            std::fs::write(&full_path, format!("content {}", i)).unwrap();
+            index.add_path(Path::new(&file_path)).unwrap();
+            index.write().unwrap();
+            let tree_oid = index.write_tree().unwrap();
+            let tree = repo.find_tree(tree_oid).unwrap();
+
+            let parent_commit = parent_oid.map(|oid| repo.find_commit(oid).unwrap());
+            let parents: Vec<&git2::Commit> = parent_commit.iter().collect();
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &sig,
+                    &sig,
+                    &format!("Commit {}", i),
+                    &tree,
+                    &parents,
+                )
+                .unwrap();
+
+            parent_oid = Some(oid);
+        }
+    }
+
+    // Make sure HEAD points to refs/heads/main
+    if num_commits > 0 {
+        let head = repo.head().unwrap();
+        if head.shorthand() != Some("main") {
+            let mut branch = repo
+                .find_branch(head.shorthand().unwrap(), git2::BranchType::Local)
+                .unwrap();
+            branch.rename("main", true).unwrap();
+        }
+    }
+
+    (dir, repo)
+}
+
+/// Commit `files` (path, contents or `None` to delete) on top of HEAD.
+pub fn commit_files(repo: &Repository, files: &[(&str, Option<&[u8]>)], message: &str) -> Oid {
+    let workdir = repo.workdir().unwrap().to_path_buf();
+    let mut index = repo.index().unwrap();
+    for (path, contents) in files {
+        match contents {
+            Some(contents) => {
+                std::fs::write(workdir.join(path), contents).unwrap();
+                index.add_path(Path::new(path)).unwrap();
+            }
+            None => {
+                std::fs::remove_file(workdir.join(path)).unwrap();
+                index.remove_path(Path::new(path)).unwrap();
+            }
+        }
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = Signature::now("Test User", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])
+        .unwrap()
+}
+
+/// Signs with a fixed fake signature block, for tests of re-signing.
+pub struct FakeSigner;
+
+impl CommitSigner for FakeSigner {
+    fn sign(&self, commit_buffer: &str) -> Result<String, Error> {
+        Ok(format!(
+            "-----BEGIN FAKE SIGNATURE-----\n{}\n-----END FAKE SIGNATURE-----",
+            commit_buffer.len()
+        ))
+    }
+}
+
+/// Append a commit signed by `FakeSigner` on top of HEAD.
+pub fn add_signed_commit(repo: &Repository) -> Oid {
+    let head_oid = repo.head().unwrap().target().unwrap();
+    let parent = repo.find_commit(head_oid).unwrap();
+    let tree = parent.tree().unwrap();
+    let sig = Signature::now("Test User", "test@example.com").unwrap();
+    let buffer = repo
+        .commit_create_buffer(&sig, &sig, "Signed commit", &tree, &[&parent])
+        .unwrap();
+    let content = std::str::from_utf8(&buffer).unwrap();
+    let oid = repo
+        .commit_signed(content, &FakeSigner.sign(content).unwrap(), None)
+        .unwrap();
+    repo.reference("refs/heads/main", oid, true, "signed")
+        .unwrap();
+    oid
+}
@@ -0,0 +1,553 @@
+use crate::error::Error;
+use crate::filter::CommitSelection;
+use crate::rewrite::{
+    execute_rewrite, plan_rewrite, CommitAction, CommitEdit, RewriteOptions, RewriteResult,
+};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+
+/// One `Key: value` trailer from the last paragraph of a commit message.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Trailer {
+    pub key: String,
+    /// The value with surrounding whitespace removed; continuation lines are
+    /// joined with a single space.
+    pub value: String,
+}
+
+/// A change to the trailers of a message. Keys are compared case-insensitively,
+/// like git does.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TrailerEdit {
+    /// Append a trailer after the existing ones, unless an identical one
+    /// (same key and value) is already there.
+    Add { key: String, value: String },
+    /// Remove every trailer with this key, or only those with this value.
+    Remove { key: String, value: Option<String> },
+    /// Give the first trailer with this key a new value and remove the others,
+    /// or append it if the key is not present.
+    Replace { key: String, value: String },
+}
+
+/// Trailer lines git adds itself; a block containing one of them may also hold
+/// free-form lines, as long as at least a quarter of the lines are trailers.
+const GIT_GENERATED_PREFIXES: &[&str] = &["Signed-off-by: ", "(cherry picked from commit "];
+
+enum BlockLine {
+    Trailer { trailer: Trailer, raw: String },
+    Other(String),
+}
+
+/// A message split into everything before the trailer block (kept byte for
+/// byte), the block's lines and the trailing whitespace after it.
+struct SplitMessage<'m> {
+    body: &'m str,
+    lines: Vec<BlockLine>,
+    tail: &'m str,
+}
+
+/// `Key: value` with a key made of letters, digits and dashes, optionally
+/// followed by spaces before the colon.
+fn split_trailer_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim_end();
+    let valid_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid_key.then(|| (key, value.trim()))
+}
+
+/// Find the trailer block using git's rules: it is the last paragraph, never
+/// the first one (the subject), and it either consists only of trailers or
+/// contains a git-generated line and at least 25% trailers.
+fn split_message(message: &str) -> SplitMessage<'_> {
+    let content = message.trim_end();
+    let tail = &message[content.len()..];
+    let no_block = SplitMessage {
+        body: content,
+        lines: Vec::new(),
+        tail,
+    };
+
+    let mut block_start = None;
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim().is_empty() {
+            block_start = Some(offset);
+        }
+    }
+    let Some(block_start) = block_start.filter(|start| *start < content.len()) else {
+        return no_block;
+    };
+    if content[..block_start].trim().is_empty() {
+        return no_block;
+    }
+
+    let mut lines: Vec<BlockLine> = Vec::new();
+    let (mut trailer_lines, mut other_lines, mut git_generated) = (0, 0, false);
+    for line in content[block_start..].split('\n') {
+        let is_continuation = line.starts_with([' ', '\t']);
+        match lines.last_mut() {
+            Some(BlockLine::Trailer { trailer, raw }) if is_continuation => {
+                trailer.value = format!("{} {}", trailer.value, line.trim())
+                    .trim()
+                    .to_string();
+                raw.push('\n');
+                raw.push_str(line);
+                continue;
+            }
+            Some(BlockLine::Other(raw)) if is_continuation => {
+                raw.push('\n');
+                raw.push_str(line);
+                continue;
+            }
+            _ => {}
+        }
+        git_generated |= GIT_GENERATED_PREFIXES.iter().any(|p| line.starts_with(p));
+        match split_trailer_line(line) {
+            Some((key, value)) => {
+                trailer_lines += 1;
+                lines.push(BlockLine::Trailer {
+                    trailer: Trailer {
+                        key: key.to_string(),
+                        value: value.to_string(),
+                    },
+                    raw: line.to_string(),
+                });
+            }
+            None => {
+                other_lines += 1;
+                lines.push(BlockLine::Other(line.to_string()));
+            }
+        }
+    }
+
+    let is_block = trailer_lines > 0
+        && (other_lines == 0 || (git_generated && trailer_lines * 3 >= other_lines));
+    if !is_block {
+        return no_block;
+    }
+    SplitMessage {
+        body: &message[..block_start],
+        lines,
+        tail,
+    }
+}
+
+/// Parse the trailers of a commit message, in order.
+pub fn parse_trailers(message: &str) -> Vec<Trailer> {
+    split_message(message)
+        .lines
+        .into_iter()
+        .filter_map(|line| match line {
+            BlockLine::Trailer { trailer, .. } => Some(trailer),
+            BlockLine::Other(_) => None,
+        })
+        .collect()
+}
+
+/// Apply trailer edits to a message. Everything before the trailer block and
+/// every trailer the edits do not touch is kept byte for byte; a block is
+/// created after a blank line when the message has none, and removed again
+/// with its blank line when the last of its lines goes.
+pub fn apply_trailer_edits(message: &str, edits: &[TrailerEdit]) -> String {
+    if edits.is_empty() {
+        return message.to_string();
+    }
+    let SplitMessage {
+        body,
+        mut lines,
+        tail,
+    } = split_message(message);
+    let new_line = |key: &str, value: &str| BlockLine::Trailer {
+        trailer: Trailer {
+            key: key.to_string(),
+            value: value.to_string(),
+        },
+        raw: format!("{}: {}", key, value),
+    };
+    let has_key = |line: &BlockLine, key: &str| matches!(line, BlockLine::Trailer { trailer, .. } if trailer.key.eq_ignore_ascii_case(key));
+
+    for edit in edits {
+        match edit {
+            TrailerEdit::Add { key, value } => {
+                let exists = lines.iter().any(|line| {
+                    matches!(line, BlockLine::Trailer { trailer, .. } if trailer.key.eq_ignore_ascii_case(key) && trailer.value == *value)
+                });
+                if !exists {
+                    lines.push(new_line(key, value));
+                }
+            }
+            TrailerEdit::Remove { key, value } => lines.retain(|line| match line {
+                BlockLine::Trailer { trailer, .. } => {
+                    let value_matches = match value {
+                        Some(value) => *value == trailer.value,
+                        None => true,
+                    };
+                    !(trailer.key.eq_ignore_ascii_case(key) && value_matches)
+                }
+                BlockLine::Other(_) => true,
+            }),
+            TrailerEdit::Replace { key, value } => {
+                match lines.iter().position(|line| has_key(line, key)) {
+                    Some(first) => {
+                        lines[first] = new_line(key, value);
+                        let mut index = 0;
+                        lines.retain(|line| {
+                            index += 1;
+                            index - 1 == first || !has_key(line, key)
+                        });
+                    }
+                    None => lines.push(new_line(key, value)),
+                }
+            }
+        }
+    }
+
+    let block: Vec<&str> = lines
+        .iter()
+        .map(|line| match line {
+            BlockLine::Trailer { raw, .. } | BlockLine::Other(raw) => raw.as_str(),
+        })
+        .collect();
+    if block.is_empty() {
+        return format!("{}{}", body.trim_end(), tail);
+    }
+    let separator = if body.trim_end().len() == body.len() {
+        "\n\n"
+    } else {
+        ""
+    };
+    format!("{}{}{}{}", body, separator, block.join("\n"), tail)
+}
+
+/// Apply the same trailer edits to every selected commit in one walk, e.g.
+/// add `Signed-off-by` to everything in `main..HEAD` or strip `Change-Id`
+/// from the whole history. Commits whose message the edits leave unchanged,
+/// such as ones that already carry an added trailer, are only re-parented.
+pub fn rewrite_trailers(
+    repo: &Repository,
+    selection: &CommitSelection,
+    edits: &[TrailerEdit],
+    options: &RewriteOptions,
+    on_progress: &dyn Fn(usize, usize),
+) -> Result<RewriteResult, Error> {
+    if edits.is_empty() {
+        return Err(Error::invalid_input("edits", "No trailer edits given"));
+    }
+    for (i, edit) in edits.iter().enumerate() {
+        let key = match edit {
+            TrailerEdit::Add { key, .. }
+            | TrailerEdit::Remove { key, .. }
+            | TrailerEdit::Replace { key, .. } => key,
+        };
+        if split_trailer_line(&format!("{}:", key)).is_none() {
+            return Err(Error::invalid_input(
+                format!("edits[{}].key", i),
+                format!("'{}' is not a valid trailer key", key),
+            ));
+        }
+    }
+    let matcher = selection.matcher(repo)?;
+    let plan = plan_rewrite(repo, &options.refs)?;
+
+    let outcome = execute_rewrite(
+        repo,
+        &plan,
+        &mut |commit| {
+            // Messages that are not UTF-8 are left alone rather than mangled
+            let Some(message) = commit.message() else {
+                return Ok(CommitAction::Keep);
+            };
+            if !matcher.matches(repo, commit)? {
+                return Ok(CommitAction::Keep);
+            }
+            let new_message = apply_trailer_edits(message, edits);
+            if new_message == message {
+                return Ok(CommitAction::Keep);
+            }
+            Ok(CommitAction::Edit {
+                edit: CommitEdit {
+                    message: Some(new_message),
+                    ..Default::default()
+                },
+                tree: None,
+            })
+        },
+        "rewrote trailers",
+        options,
+        on_progress,
+    )?;
+
+    if outcome.edited.is_empty() {
+        return Err(Error::nothing_to_rewrite(
+            "No selected commit needs these trailer changes",
+        ));
+    }
+    outcome.into_result(None, options.dry_run)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::CommitFilters;
+    use crate::repo::{commit_detail, list_commits};
+    use crate::rewrite::rewrite_commits;
+    use crate::test_support::create_test_repo;
+    use git2::Oid;
+
+    fn trailer(key: &str, value: &str) -> Trailer {
+        Trailer {
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_trailers_matches_libgit2() {
+        let messages = [
+            "Subject\n\nBody text.\n\nSigned-off-by: A <a@example.com>\nCo-authored-by: B <b@example.com>\n",
+            "Subject\n\nReviewed-by: C\n",
+            "Subject only\n",
+            "Subject\n\nNot: a block\nbecause this line is prose\n",
+            "Subject\n\nSome notes\nSigned-off-by: A <a@example.com>\n",
+        ];
+        for message in messages {
+            let ours: Vec<(String, String)> = parse_trailers(message)
+                .into_iter()
+                .map(|t| (t.key, t.value))
+                .collect();
+            let libgit2 = git2::message_trailers_strs(message).unwrap();
+            let theirs: Vec<(String, String)> = libgit2
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            assert_eq!(ours, theirs, "{:?}", message);
+        }
+        let folded = parse_trailers("Subject\n\nFixes: a very\n  long line\n");
+        assert_eq!(folded, [trailer("Fixes", "a very long line")]);
+    }
+
+    #[test]
+    fn test_trailer_edits_keep_body_intact() {
+        let message = "Fix: parser\n\nKeep  this  body\n  exactly.\n\nSigned-off-by: A <a@example.com>\nCo-authored-by: B <b@example.com>\nCo-authored-by: C <c@example.com>\n";
+        let edited = apply_trailer_edits(
+            message,
+            &[
+                TrailerEdit::Remove {
+                    key: "co-authored-by".to_string(),
+                    value: Some("B <b@example.com>".to_string()),
+                },
+                TrailerEdit::Add {
+                    key: "Reviewed-by".to_string(),
+                    value: "D <d@example.com>".to_string(),
+                },
+                TrailerEdit::Replace {
+                    key: "Signed-off-by".to_string(),
+                    value: "E <e@example.com>".to_string(),
+                },
+            ],
+        );
+        assert_eq!(
+            edited,
+            "Fix: parser\n\nKeep  this  body\n  exactly.\n\nSigned-off-by: E <e@example.com>\nCo-authored-by: C <c@example.com>\nReviewed-by: D <d@example.com>\n"
+        );
+        assert_eq!(
+            parse_trailers(&edited),
+            [
+                trailer("Signed-off-by", "E <e@example.com>"),
+                trailer("Co-authored-by", "C <c@example.com>"),
+                trailer("Reviewed-by", "D <d@example.com>"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_adding_and_removing_trailers_round_trips() {
+        for message in [
+            "Subject",
+            "Subject\n\nBody\n",
+            "Subject\n\nKey: not a trailer\nline\n",
+        ] {
+            let added = apply_trailer_edits(
+                message,
+                &[TrailerEdit::Replace {
+                    key: "Signed-off-by".to_string(),
+                    value: "A <a@example.com>".to_string(),
+                }],
+            );
+            assert_eq!(
+                parse_trailers(&added),
+                [trailer("Signed-off-by", "A <a@example.com>")]
+            );
+            let removed = apply_trailer_edits(
+                &added,
+                &[TrailerEdit::Remove {
+                    key: "signed-off-by".to_string(),
+                    value: None,
+                }],
+            );
+            assert_eq!(removed, message);
+        }
+    }
+
+    fn sign_off() -> TrailerEdit {
+        TrailerEdit::Add {
+            key: "Signed-off-by".to_string(),
+            value: "Test User <test@example.com>".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rewrite_trailers_over_range_dedupes() {
+        let (_dir, repo) = create_test_repo(4);
+        let commits = list_commits(&repo, 0, 10).unwrap();
+        // Commit 2 is already signed off
+        let signed = Oid::from_str(&commits[1].oid).unwrap();
+        let signed_message =
+            "Commit 2\n\nSigned-off-by: Test User <test@example.com>\nChange-Id: I123\n";
+        rewrite_commits(
+            &repo,
+            &[(
+                signed,
+                CommitEdit {
+                    message: Some(signed_message.to_string()),
+                    ..Default::default()
+                },
+            )],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+
+        let base = &commits[2].oid;
+        let selection = CommitSelection {
+            range: Some(format!("{}..HEAD", base)),
+            ..Default::default()
+        };
+        let result = rewrite_trailers(
+            &repo,
+            &selection,
+            &[sign_off()],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        // Only Commit 3 needed the trailer; Commit 2 already had it
+        assert_eq!(result.edited_commits.len(), 1);
+
+        let after = list_commits(&repo, 0, 10).unwrap();
+        let trailer_counts: Vec<usize> = after
+            .iter()
+            .map(|c| {
+                commit_detail(&repo, Oid::from_str(&c.oid).unwrap())
+                    .unwrap()
+                    .trailers
+                    .len()
+            })
+            .collect();
+        assert_eq!(trailer_counts, [1, 2, 0, 0]);
+
+        let strip = TrailerEdit::Remove {
+            key: "change-id".to_string(),
+            value: None,
+        };
+        let all = CommitSelection::default();
+        rewrite_trailers(
+            &repo,
+            &all,
+            std::slice::from_ref(&strip),
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        let second = commit_detail(
+            &repo,
+            Oid::from_str(&list_commits(&repo, 0, 10).unwrap()[1].oid).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            second.message,
+            "Commit 2\n\nSigned-off-by: Test User <test@example.com>\n"
+        );
+
+        let again = rewrite_trailers(
+            &repo,
+            &all,
+            &[strip],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(again.kind(), "nothing_to_rewrite");
+    }
+
+    #[test]
+    fn test_rewrite_trailers_by_filters_and_validation() {
+        let (_dir, repo) = create_test_repo(3);
+        let selection = CommitSelection {
+            filters: Some(CommitFilters {
+                message_pattern: Some("commit 1".to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = rewrite_trailers(
+            &repo,
+            &selection,
+            &[sign_off()],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .unwrap();
+        assert_eq!(result.edited_commits.len(), 1);
+        let messages: Vec<String> = list_commits(&repo, 0, 10)
+            .unwrap()
+            .iter()
+            .map(|c| {
+                commit_detail(&repo, Oid::from_str(&c.oid).unwrap())
+                    .unwrap()
+                    .message
+            })
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Commit 2",
+                "Commit 1\n\nSigned-off-by: Test User <test@example.com>",
+                "Commit 0"
+            ]
+        );
+
+        let bad_range = CommitSelection {
+            range: Some("nope..HEAD".to_string()),
+            ..Default::default()
+        };
+        let err = rewrite_trailers(
+            &repo,
+            &bad_range,
+            &[sign_off()],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.field(), Some("range"));
+
+        let bad_key = TrailerEdit::Remove {
+            key: "Not a key".to_string(),
+            value: None,
+        };
+        let err = rewrite_trailers(
+            &repo,
+            &selection,
+            &[bad_key],
+            &RewriteOptions::default(),
+            &|_, _| {},
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.field(), Some("edits[0].key"));
+    }
+}
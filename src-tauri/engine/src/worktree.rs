@@ -0,0 +1,395 @@
+use crate::error::{access_denied, Error, GitErrorInfo};
+use git2::build::CheckoutBuilder;
+use git2::{
+    CheckoutNotificationType, Commit, DiffOptions, Oid, Repository, ResetType, Signature,
+    StatusOptions,
+};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Keeps the index and working tree in step with HEAD's branch when a rewrite
+/// or restore gives it a different tree, the way `git reset --keep` does:
+/// files that differ between the old and new tree are updated, other local
+/// changes are kept, and a local change to an updated file aborts the move.
+pub(crate) struct WorktreeSync<'r> {
+    repo: &'r Repository,
+    old_tree: Oid,
+    changed_paths: Vec<PathBuf>,
+    /// The stash entry holding the local changes, when they were auto-stashed.
+    stash: Option<Oid>,
+}
+
+/// Local changes found before a rewrite that moves HEAD's branch, so the walk
+/// can stop as soon as it rewrites a file with local changes instead of only
+/// when the working tree is updated at the end.
+pub(crate) struct DirtyHead<'r> {
+    repo: &'r Repository,
+    tip: Oid,
+    paths: Vec<String>,
+}
+
+impl<'r> DirtyHead<'r> {
+    /// `None` unless the branch HEAD points to is among `ref_targets`, the
+    /// repository has a working tree and some tracked file has local changes.
+    pub(crate) fn find(
+        repo: &'r Repository,
+        ref_targets: &[(String, Oid)],
+    ) -> Result<Option<Self>, Error> {
+        if repo.is_bare() {
+            return Ok(None);
+        }
+        let head = repo.find_reference("HEAD")?;
+        let Some(branch) = head.symbolic_target() else {
+            return Ok(None);
+        };
+        let Some((_, tip)) = ref_targets.iter().find(|(name, _)| name == branch) else {
+            return Ok(None);
+        };
+        let paths = dirty_paths(repo)?;
+        Ok((!paths.is_empty()).then_some(DirtyHead {
+            repo,
+            tip: *tip,
+            paths,
+        }))
+    }
+
+    /// Fail with `DirtyWorktree` if giving `commit`, which is part of HEAD's
+    /// history, the tree `new_tree` changes a file with local changes.
+    pub(crate) fn check(&self, commit: &Commit, new_tree: Oid) -> Result<(), Error> {
+        if new_tree == commit.tree_id() {
+            return Ok(());
+        }
+        if commit.id() != self.tip && !self.repo.graph_descendant_of(self.tip, commit.id())? {
+            return Ok(());
+        }
+        let mut options = DiffOptions::new();
+        options.disable_pathspec_match(true);
+        for path in &self.paths {
+            options.pathspec(path);
+        }
+        let diff = self.repo.diff_tree_to_tree(
+            Some(&commit.tree()?),
+            Some(&self.repo.find_tree(new_tree)?),
+            Some(&mut options),
+        )?;
+        let paths: BTreeSet<String> = diff
+            .deltas()
+            .flat_map(|delta| [delta.old_file().path(), delta.new_file().path()])
+            .flatten()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        if paths.is_empty() {
+            return Ok(());
+        }
+        Err(Error::DirtyWorktree {
+            paths: paths.into_iter().collect(),
+            unapplied_stash: None,
+        })
+    }
+}
+
+/// Paths with staged or unstaged changes to tracked files, sorted.
+fn dirty_paths(repo: &Repository) -> Result<Vec<String>, Error> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(false)
+        .include_ignored(false)
+        .exclude_submodules(true);
+    let statuses = repo.statuses(Some(&mut options))?;
+    let paths: BTreeSet<String> = statuses
+        .iter()
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect();
+    Ok(paths.into_iter().collect())
+}
+
+fn stash_signature(repo: &Repository) -> Result<Signature<'static>, Error> {
+    match repo.signature() {
+        Ok(signature) => Ok(signature.to_owned()),
+        Err(_) => Ok(Signature::now(
+            "git-history-editor",
+            "git-history-editor@localhost",
+        )?),
+    }
+}
+
+/// Check out `tree` over the working tree, using HEAD as the baseline.
+/// Nothing is written if a file to update has local changes or an untracked
+/// file is in the way; those paths are returned instead.
+fn checkout_keep(repo: &Repository, tree: Oid, paths: &[PathBuf]) -> Result<Vec<String>, Error> {
+    let mut conflicts = BTreeSet::new();
+    let result = {
+        let mut checkout = CheckoutBuilder::new();
+        checkout
+            .safe()
+            .notify_on(CheckoutNotificationType::CONFLICT)
+            .notify(|_, path, _, _, _| {
+                if let Some(path) = path {
+                    conflicts.insert(path.to_string_lossy().into_owned());
+                }
+                true
+            });
+        repo.checkout_tree(repo.find_tree(tree)?.as_object(), Some(&mut checkout))
+    };
+    match result {
+        Ok(()) => Ok(Vec::new()),
+        Err(_) if !conflicts.is_empty() => Ok(conflicts.into_iter().collect()),
+        Err(e) => Err(checkout_error(repo, paths, e)),
+    }
+}
+
+/// Classify a failed checkout of `paths`, naming the first file, directory
+/// or index that could not be written.
+fn checkout_error(repo: &Repository, paths: &[PathBuf], e: git2::Error) -> Error {
+    let Some(workdir) = repo.workdir() else {
+        return e.into();
+    };
+    let files: Vec<PathBuf> = paths.iter().map(|path| workdir.join(path)).collect();
+    let index = repo.path().join("index");
+    let denied = files
+        .iter()
+        .flat_map(|file| [Some(file.as_path()), file.parent()])
+        .flatten()
+        .chain([index.as_path()])
+        .find(|path| access_denied(&e, path, true))
+        .map(Path::to_path_buf);
+    match denied {
+        Some(path) => Error::PermissionDenied {
+            path: Some(path.to_string_lossy().into_owned()),
+            git: GitErrorInfo::from(&e),
+        },
+        None => e.into(),
+    }
+}
+
+/// Prepare moving the refs in `moves` (ref, old tip, new tip). Does nothing
+/// unless one of them is the branch HEAD points to, the repository has a
+/// working tree and the branch's tree changes. Otherwise the new tree is
+/// checked out while HEAD still points at the old tip, so the move can be
+/// rolled back. With `auto_stash` local changes are stashed first and
+/// re-applied by `finish`; without it, or if they cannot be stashed, local
+/// changes to the updated files fail with `DirtyWorktree`, which then carries
+/// any stash entry that could not be re-applied.
+pub(crate) fn prepare_worktree<'r>(
+    repo: &'r Repository,
+    moves: &[(String, Oid, Oid)],
+    auto_stash: bool,
+) -> Result<Option<WorktreeSync<'r>>, Error> {
+    if repo.is_bare() {
+        return Ok(None);
+    }
+    let head = repo.find_reference("HEAD")?;
+    let Some(branch) = head.symbolic_target() else {
+        return Ok(None);
+    };
+    let Some((_, old_tip, new_tip)) = moves.iter().find(|(name, _, _)| name == branch) else {
+        return Ok(None);
+    };
+    // An unborn or deleted branch has no tree to keep in step with
+    if old_tip.is_zero() || new_tip.is_zero() {
+        return Ok(None);
+    }
+    let old_tree = repo.find_commit(*old_tip)?.tree()?;
+    let new_tree = repo.find_commit(*new_tip)?.tree()?;
+    if old_tree.id() == new_tree.id() {
+        return Ok(None);
+    }
+
+    let mut changed_paths = Vec::new();
+    repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?
+        .foreach(
+            &mut |delta, _| {
+                changed_paths.extend(delta.old_file().path().map(PathBuf::from));
+                changed_paths.extend(delta.new_file().path().map(PathBuf::from));
+                true
+            },
+            None,
+            None,
+            None,
+        )?;
+    changed_paths.dedup();
+
+    let dirty = dirty_paths(repo)?;
+    let mut stash = None;
+    if !dirty.is_empty() && auto_stash {
+        // Stashing moves `refs/stash`, which a rewrite of the stash itself must not race with
+        if moves.iter().any(|(name, _, _)| name == "refs/stash") {
+            return Err(Error::DirtyWorktree {
+                paths: dirty,
+                unapplied_stash: None,
+            });
+        }
+        // Stashing needs a mutable handle; a second one on the same repository will do
+        let mut stasher = Repository::open(repo.path())?;
+        stash = Some(stasher.stash_save(
+            &stash_signature(repo)?,
+            "git-history-editor: auto-stash before rewrite",
+            None,
+        )?);
+    }
+
+    let sync = WorktreeSync {
+        repo,
+        old_tree: old_tree.id(),
+        changed_paths,
+        stash,
+    };
+    let conflicts = checkout_keep(repo, new_tree.id(), &sync.changed_paths)?;
+    if !conflicts.is_empty() {
+        return Err(Error::DirtyWorktree {
+            paths: conflicts,
+            unapplied_stash: sync.restore_stash()?,
+        });
+    }
+    Ok(Some(sync))
+}
+
+impl WorktreeSync<'_> {
+    /// The refs were not moved after all: put the old tree back and re-apply
+    /// the stashed changes.
+    pub(crate) fn roll_back(self) -> Result<(), Error> {
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().remove_untracked(true);
+        for path in &self.changed_paths {
+            checkout.path(path);
+        }
+        self.repo
+            .checkout_tree(
+                self.repo.find_tree(self.old_tree)?.as_object(),
+                Some(&mut checkout),
+            )
+            .map_err(|e| checkout_error(self.repo, &self.changed_paths, e))?;
+        self.restore_stash()?;
+        Ok(())
+    }
+
+    /// The refs were moved: re-apply the stashed changes on the new tree.
+    /// Returns the stash OID if they conflict with it; the working tree is
+    /// then left clean and the changes stay in the stash list.
+    pub(crate) fn finish(self) -> Result<Option<String>, Error> {
+        self.restore_stash()
+    }
+
+    fn restore_stash(&self) -> Result<Option<String>, Error> {
+        let Some(stash) = self.stash else {
+            return Ok(None);
+        };
+        let mut stasher = Repository::open(self.repo.path())?;
+        if stasher.stash_apply(0, None).is_err() {
+            return Ok(Some(stash.to_string()));
+        }
+        if stasher.index()?.has_conflicts() {
+            // Unlike `git stash pop`, leave no conflict markers behind: the
+            // changes are all still in the stash entry
+            let head = stasher.head()?.peel_to_commit()?;
+            stasher.reset(head.as_object(), ResetType::Hard, None)?;
+            return Ok(Some(stash.to_string()));
+        }
+        stasher.stash_drop(0)?;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::undo_rewrite;
+    use crate::purge::{remove_paths, RemovePathsOptions};
+    use crate::rewrite::RewriteOptions;
+    use crate::test_support::{commit_files, create_test_repo};
+
+    fn repo_with_secret() -> (tempfile::TempDir, Repository) {
+        let (dir, repo) = create_test_repo(1);
+        commit_files(
+            &repo,
+            &[("secret.txt", Some(b"secret")), ("app.txt", Some(b"v1"))],
+            "Add files",
+        );
+        (dir, repo)
+    }
+
+    /// Remove `secret.txt` from history; returns the unapplied stash, if any.
+    fn remove_secret(repo: &Repository, auto_stash: bool) -> Result<Option<String>, Error> {
+        let remove = RemovePathsOptions {
+            paths: vec!["secret.txt".to_string()],
+            prune_empty: false,
+        };
+        let options = RewriteOptions {
+            auto_stash,
+            ..Default::default()
+        };
+        remove_paths(repo, &remove, &options, &|_, _| {})
+            .map(|result| result.rewrite.unapplied_stash)
+    }
+
+    fn read(dir: &tempfile::TempDir, path: &str) -> Option<String> {
+        std::fs::read_to_string(dir.path().join(path)).ok()
+    }
+
+    #[test]
+    fn test_tree_changing_rewrite_updates_worktree_and_keeps_other_changes() {
+        let (dir, repo) = repo_with_secret();
+        std::fs::write(dir.path().join("app.txt"), "local edit").unwrap();
+
+        remove_secret(&repo, false).unwrap();
+        assert_eq!(read(&dir, "secret.txt"), None);
+        assert_eq!(read(&dir, "app.txt").as_deref(), Some("local edit"));
+        assert_eq!(dirty_paths(&repo).unwrap(), ["app.txt"]);
+
+        // Undoing puts the file back the same way
+        undo_rewrite(&repo, None).unwrap();
+        assert_eq!(read(&dir, "secret.txt").as_deref(), Some("secret"));
+        assert_eq!(dirty_paths(&repo).unwrap(), ["app.txt"]);
+    }
+
+    #[test]
+    fn test_local_changes_to_updated_files_refuse_or_stash() {
+        let (dir, repo) = repo_with_secret();
+        let head = repo.head().unwrap().target();
+        std::fs::write(dir.path().join("secret.txt"), "edited secret").unwrap();
+
+        let dry = RewriteOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let remove = RemovePathsOptions {
+            paths: vec!["secret.txt".to_string()],
+            prune_empty: false,
+        };
+        let preview = remove_paths(&repo, &remove, &dry, &|_, _| {}).unwrap();
+        let err = remove_secret(&repo, false).unwrap_err();
+        assert_eq!(err.kind(), "dirty_worktree");
+        assert_eq!(err.paths().unwrap(), ["secret.txt"]);
+        assert_eq!(repo.head().unwrap().target(), head);
+        // The walk stopped at the first commit that removes the file
+        let new_tip = Oid::from_str(&preview.rewrite.updated_refs[0].new_oid).unwrap();
+        assert!(repo.find_commit(new_tip).is_err());
+        assert_eq!(read(&dir, "secret.txt").as_deref(), Some("edited secret"));
+
+        // The stashed edit conflicts with the removal, so it stays stashed
+        std::fs::write(dir.path().join("app.txt"), "local edit").unwrap();
+        let stash = remove_secret(&repo, true).unwrap();
+        assert_ne!(repo.head().unwrap().target(), head);
+        assert_eq!(read(&dir, "secret.txt"), None);
+        assert_eq!(read(&dir, "app.txt").as_deref(), Some("v1"));
+        assert!(dirty_paths(&repo).unwrap().is_empty());
+        let stash_ref = repo.find_reference("refs/stash").unwrap();
+        assert_eq!(stash, stash_ref.target().map(|oid| oid.to_string()));
+    }
+
+    #[test]
+    fn test_auto_stash_reapplies_changes() {
+        let (dir, repo) = repo_with_secret();
+        std::fs::write(dir.path().join("app.txt"), "local edit").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "edited secret").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(std::path::Path::new("app.txt")).unwrap();
+        index.write().unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "secret").unwrap();
+
+        assert_eq!(remove_secret(&repo, true).unwrap(), None);
+        assert_eq!(read(&dir, "secret.txt"), None);
+        assert_eq!(read(&dir, "app.txt").as_deref(), Some("local edit"));
+        assert!(repo.find_reference("refs/stash").is_err());
+    }
+}
@@ -13,9 +13,30 @@ pub fn run() {
             git_commands::get_commits,
             git_commands::get_commits_filtered,
             git_commands::get_commit_detail,
+            git_commands::get_changed_files,
+            git_commands::get_file_patch,
+            git_commands::published_refs,
             git_commands::update_commit,
+            git_commands::update_commits,
+            git_commands::squash_commits,
+            git_commands::reorder_commits,
+            git_commands::split_commit,
+            git_commands::drop_commits,
+            git_commands::remove_paths,
+            git_commands::scrub_blobs,
+            git_commands::rewrite_trailers,
+            git_commands::rewrite_dates,
+            git_commands::preview_identity_rewrite,
+            git_commands::rewrite_identities,
+            git_commands::load_mailmap,
+            git_commands::preview_message_rewrite,
+            git_commands::rewrite_messages,
             git_commands::check_backup,
             git_commands::restore_backup,
+            git_commands::list_backups,
+            git_commands::redo_restore,
+            git_commands::delete_backup,
+            git_commands::prune_backups,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -1,5 +1,64 @@
 import { invoke } from "@tauri-apps/api/core";
 
+export type ErrorKind =
+  | "not_a_repository"
+  | "detached_head"
+  | "ref_not_found"
+  | "commit_not_in_history"
+  | "invalid_oid"
+  | "invalid_signature"
+  | "invalid_input"
+  | "duplicate_edit"
+  | "nothing_to_rewrite"
+  | "signing_failed"
+  | "merge_conflict"
+  | "already_published"
+  | "ref_moved"
+  | "dirty_worktree"
+  | "non_monotonic_date"
+  | "permission_denied"
+  | "ref_update_failed"
+  | "backup_not_found"
+  | "nothing_to_redo"
+  | "io"
+  | "git"
+  | "internal";
+
+export interface GitErrorInfo {
+  class: string;
+  code: string;
+  raw_code: number;
+  message: string;
+}
+
+/** The error every command rejects with. */
+export interface GitError {
+  kind: ErrorKind;
+  message: string;
+  field?: string;
+  oid?: string;
+  /** Parent that the commit of a `non_monotonic_date` would predate. */
+  parent_oid?: string;
+  ref_name?: string;
+  /** Conflicting paths of a `merge_conflict`. */
+  paths?: string[];
+  /** Remote-tracking refs and tags that contain the commit of an `already_published`,
+   *  or the refs that keep the rewritten history of a `ref_moved`. */
+  refs?: string[];
+  /** Auto-stash entry a `dirty_worktree` could not re-apply and left in the stash list. */
+  unapplied_stash?: string;
+  git?: GitErrorInfo;
+}
+
+export function isGitError(e: unknown): e is GitError {
+  return typeof e === "object" && e !== null && "kind" in e && "message" in e;
+}
+
+/** Human-readable text for a rejected command. */
+export function errorMessage(e: unknown): string {
+  return isGitError(e) ? e.message : String(e);
+}
+
 export interface RepoInfo {
   path: string;
   branch: string;
@@ -27,18 +86,95 @@ export interface CommitDetail {
   committer_offset: number;
   parent_oids: string[];
   is_merge: boolean;
+  /** Message trailers (Signed-off-by, Co-authored-by, ...), in order. */
+  trailers: Trailer[];
+}
+
+export interface Trailer {
+  key: string;
+  value: string;
+}
+
+/** Keys match case-insensitively. `replace` appends the trailer when the key is missing. */
+export type TrailerEdit =
+  | { op: "add"; key: string; value: string }
+  | { op: "remove"; key: string; value?: string | null }
+  | { op: "replace"; key: string; value: string };
+
+export interface RefUpdate {
+  ref_name: string;
+  old_oid: string;
+  new_oid: string;
+}
+
+export interface OidMapping {
+  old_oid: string;
+  new_oid: string;
+}
+
+export interface CommitChange {
+  old_oid: string;
+  new_oid: string;
+  changed_fields: string[];
 }
 
 export interface RewriteResult {
   old_oid: string;
   new_oid: string;
   commits_rewritten: number;
+  updated_refs: RefUpdate[];
+  edited_commits: OidMapping[];
+  commits: CommitChange[];
+  dry_run: boolean;
+  resigned_commits: string[];
+  /** Dry run only: re-signed commits and their descendants were hashed unsigned, so their new OIDs will differ. */
+  approximate_oids: boolean;
+  lost_signatures: string[];
+  dropped_commits: string[];
+  /** Auto-stash entry that conflicted with the new HEAD and was left in the stash list. */
+  unapplied_stash: string | null;
+}
+
+export type RefSelection =
+  | "head"
+  | "local_branches"
+  | "all"
+  | { refs: string[] };
+
+/** `sign` re-signs with the repository's git signing config, like `git commit -S`. */
+export type SigningMode = "strip" | "sign";
+
+/** What happens to tags on rewritten commits. */
+export type TagRewrite =
+  | "keep"
+  | "update"
+  | { rename: { suffix: string } };
+
+export interface RewriteOptions {
+  refs?: RefSelection;
+  dry_run?: boolean;
+  signing?: SigningMode;
+  /** Rewrite commits that remote-tracking refs or tags already contain. */
+  allow_published?: boolean;
+  /** Stash local changes the new HEAD would overwrite and re-apply them afterwards. */
+  auto_stash?: boolean;
+  tags?: TagRewrite;
 }
 
 export async function openRepository(path: string): Promise<RepoInfo> {
   return invoke("open_repository", { path });
 }
 
+export interface PublishedRefs {
+  oid: string;
+  remote_refs: string[];
+  tags: string[];
+}
+
+export async function getPublishedRefs(path: string, oid: string): Promise<PublishedRefs> {
+  return invoke("published_refs", { path, oid });
+}
+
 export async function getCommits(
   path: string,
   offset: number,
@@ -72,18 +208,100 @@ export async function getCommitDetail(
   return invoke("get_commit_detail", { path, oid });
 }
 
+export type FileStatus = "added" | "deleted" | "modified" | "renamed" | "copied" | "type_changed";
+
+export interface FileChange {
+  status: FileStatus;
+  old_path: string | null;
+  new_path: string | null;
+  similarity: number | null;
+  is_binary: boolean;
+  additions: number;
+  deletions: number;
+}
+
+export interface ParentDiff {
+  parent_oid: string | null;
+  files: FileChange[];
+  additions: number;
+  deletions: number;
+}
+
+export interface FilePatch {
+  old_path: string | null;
+  new_path: string | null;
+  status: FileStatus;
+  is_binary: boolean;
+  patch: string;
+  truncated: boolean;
+  additions: number;
+  deletions: number;
+}
+
+export async function getChangedFiles(path: string, oid: string): Promise<ParentDiff[]> {
+  return invoke("get_changed_files", { path, oid });
+}
+
+export async function getFilePatch(
+  path: string,
+  oid: string,
+  filePath: string,
+  parentIndex?: number,
+  maxBytes?: number
+): Promise<FilePatch> {
+  return invoke("get_file_patch", {
+    path,
+    oid,
+    filePath,
+    parentIndex: parentIndex ?? null,
+    maxBytes: maxBytes ?? null,
+  });
+}
+
 export interface BackupInfo {
   exists: boolean;
   backup_oid: string | null;
   branch: string;
+  entry_id: string | null;
+  description: string | null;
+  can_redo: boolean;
+}
+
+export type BackupKind = "rewrite" | "undo" | "redo";
+
+export interface BackupEntry {
+  id: string;
+  timestamp: number;
+  kind: BackupKind;
+  description: string;
+  refs: { ref_name: string; backup_oid: string; rewritten_oid: string }[];
 }
 
 export async function checkBackup(path: string): Promise<BackupInfo> {
   return invoke("check_backup", { path });
 }
 
-export async function restoreBackup(path: string): Promise<string> {
-  return invoke("restore_backup", { path });
+export async function listBackups(path: string): Promise<BackupEntry[]> {
+  return invoke("list_backups", { path });
+}
+
+export async function restoreBackup(path: string, entryId?: string): Promise<string> {
+  return invoke("restore_backup", { path, entryId: entryId ?? null });
+}
+
+export async function redoRestore(path: string): Promise<string> {
+  return invoke("redo_restore", { path });
+}
+
+export async function deleteBackup(path: string, entryId: string): Promise<void> {
+  return invoke("delete_backup", { path, entryId });
+}
+
+export type BackupPrune = { older_than: number } | { keep_last: number };
+
+/** Delete old backup entries; resolves to the IDs of the deleted entries. */
+export async function pruneBackups(path: string, prune: BackupPrune): Promise<string[]> {
+  return invoke("prune_backups", { path, prune });
 }
 
 export interface UpdateCommitParams {
@@ -98,6 +316,7 @@ export interface UpdateCommitParams {
   newCommitterDate?: number;
   newCommitterOffset?: number;
   newMessage?: string;
+  options?: RewriteOptions;
 }
 
 export async function updateCommit(
@@ -106,14 +325,256 @@ export async function updateCommit(
   return invoke("update_commit", {
     path: params.path,
     oid: params.oid,
-    newAuthorName: params.newAuthorName ?? null,
-    newAuthorEmail: params.newAuthorEmail ?? null,
-    newAuthorDate: params.newAuthorDate ?? null,
-    newAuthorOffset: params.newAuthorOffset ?? null,
-    newCommitterName: params.newCommitterName ?? null,
-    newCommitterEmail: params.newCommitterEmail ?? null,
-    newCommitterDate: params.newCommitterDate ?? null,
-    newCommitterOffset: params.newCommitterOffset ?? null,
-    newMessage: params.newMessage ?? null,
+    edit: {
+      author_name: params.newAuthorName,
+      author_email: params.newAuthorEmail,
+      author_date: params.newAuthorDate,
+      author_offset: params.newAuthorOffset,
+      committer_name: params.newCommitterName,
+      committer_email: params.newCommitterEmail,
+      committer_date: params.newCommitterDate,
+      committer_offset: params.newCommitterOffset,
+      message: params.newMessage,
+    },
+    options: params.options ?? null,
   });
 }
+
+export interface CommitEdit {
+  author_name?: string;
+  author_email?: string;
+  author_date?: number;
+  author_offset?: number;
+  committer_name?: string;
+  committer_email?: string;
+  committer_date?: number;
+  committer_offset?: number;
+  message?: string;
+  /** Applied after `message`, or to the original message. */
+  trailers?: TrailerEdit[];
+}
+
+export async function updateCommits(
+  path: string,
+  edits: [string, CommitEdit][],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("update_commits", { path, edits, options: options ?? null });
+}
+
+export type SquashMode = "squash" | "fixup";
+
+export interface SquashOptions {
+  mode?: SquashMode;
+  /** Placeholders: {messages}, {first}, {subjects}, {count}. */
+  message_template?: string;
+  message?: string;
+  author_oid?: string;
+}
+
+export async function squashCommits(
+  path: string,
+  oids: string[],
+  squash?: SquashOptions,
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("squash_commits", { path, oids, squash: squash ?? null, options: options ?? null });
+}
+
+/** `newOrder` lists a run of consecutive commits, oldest first, in their new order. */
+export async function reorderCommits(
+  path: string,
+  newOrder: string[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("reorder_commits", { path, newOrder, options: options ?? null });
+}
+
+export interface SplitGroup {
+  /** Git pathspecs, e.g. "src/" or "*.md". */
+  paths: string[];
+  message: string;
+}
+
+export async function splitCommit(
+  path: string,
+  oid: string,
+  groups: SplitGroup[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("split_commit", { path, oid, groups, options: options ?? null });
+}
+
+export async function dropCommits(
+  path: string,
+  oids: string[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("drop_commits", { path, oids, options: options ?? null });
+}
+
+export interface RemovePathsOptions {
+  /** Git pathspecs, e.g. ".env", "data/" or "*.csv" (any depth). */
+  paths: string[];
+  /** Drop commits that end up without changes. */
+  prune_empty?: boolean;
+}
+
+export interface RemovePathsResult extends RewriteResult {
+  commits_affected: number;
+  blobs_removed: number;
+  removed_paths: string[];
+}
+
+export async function removePaths(
+  path: string,
+  remove: RemovePathsOptions,
+  options?: RewriteOptions
+): Promise<RemovePathsResult> {
+  return invoke("remove_paths", { path, remove, options: options ?? null });
+}
+
+/** Commits a bulk rewrite applies to; both parts must match. */
+export interface CommitSelection {
+  /** Revision range such as "main..HEAD", or one revision for everything reachable from it. */
+  range?: string | null;
+  filters?: CommitFilters | null;
+}
+
+export async function rewriteTrailers(
+  path: string,
+  selection: CommitSelection | null,
+  edits: TrailerEdit[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("rewrite_trailers", { path, selection, edits, options: options ?? null });
+}
+
+export interface DateRewrite {
+  /** Seconds added to each date; negative values move dates back. */
+  shift_seconds?: number;
+  /** Convert each date to this UTC offset in minutes, keeping the instant. */
+  offset_minutes?: number | null;
+  applies_to?: IdentityRole;
+  committer_date_is_author_date?: boolean;
+  /** Reject the rewrite with `non_monotonic_date` if a commit would become older than its parent. */
+  require_monotonic?: boolean;
+}
+
+export async function rewriteDates(
+  path: string,
+  selection: CommitSelection | null,
+  dates: DateRewrite,
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("rewrite_dates", { path, selection, dates, options: options ?? null });
+}
+
+export interface ScrubOptions {
+  /** Strings replaced exactly as written. */
+  literals?: string[];
+  /** Regular expressions (Rust regex syntax). */
+  patterns?: string[];
+  /** Defaults to "***REMOVED***"; may refer to capture groups as $1. */
+  replacement?: string | null;
+  /** Also rewrite binary files. */
+  include_binary?: boolean;
+}
+
+export interface ScrubbedCommit {
+  oid: string;
+  paths: string[];
+}
+
+export interface ScrubResult extends RewriteResult {
+  blobs_scrubbed: number;
+  matched_commits: ScrubbedCommit[];
+  matched_paths: string[];
+}
+
+export async function scrubBlobs(
+  path: string,
+  scrub: ScrubOptions,
+  options?: RewriteOptions
+): Promise<ScrubResult> {
+  return invoke("scrub_blobs", { path, scrub, options: options ?? null });
+}
+
+export type IdentityMatch =
+  | { email: string }
+  | { name: string }
+  | { name_and_email: { name: string; email: string } }
+  | { pattern: string };
+
+export type IdentityRole = "author" | "committer" | "both";
+
+export interface IdentityRule {
+  matches: IdentityMatch;
+  new_name: string | null;
+  new_email: string | null;
+  applies_to?: IdentityRole;
+}
+
+export interface IdentityRewritePreview {
+  rules: { rule_index: number; commits_matched: number }[];
+  commits_affected: number;
+  commits_to_rewrite: number;
+}
+
+export async function previewIdentityRewrite(
+  path: string,
+  rules: IdentityRule[],
+  refs?: RefSelection
+): Promise<IdentityRewritePreview> {
+  return invoke("preview_identity_rewrite", { path, rules, refs: refs ?? null });
+}
+
+export async function rewriteIdentities(
+  path: string,
+  rules: IdentityRule[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("rewrite_identities", { path, rules, options: options ?? null });
+}
+
+export interface MessageRule {
+  /** Regular expression; the replacement can use `$1` or `${name}` for capture groups. */
+  pattern: string;
+  replacement: string;
+}
+
+export interface MessageChange {
+  oid: string;
+  before: string;
+  after: string;
+}
+
+export interface MessageRewritePreview {
+  changes: MessageChange[];
+  commits_to_rewrite: number;
+}
+
+export async function previewMessageRewrite(
+  path: string,
+  selection: CommitSelection | null,
+  rules: MessageRule[],
+  refs?: RefSelection
+): Promise<MessageRewritePreview> {
+  return invoke("preview_message_rewrite", { path, selection, rules, refs: refs ?? null });
+}
+
+export async function rewriteMessages(
+  path: string,
+  selection: CommitSelection | null,
+  rules: MessageRule[],
+  options?: RewriteOptions
+): Promise<RewriteResult> {
+  return invoke("rewrite_messages", { path, selection, rules, options: options ?? null });
+}
+
+export async function loadMailmap(
+  path: string,
+  mailmapPath?: string
+): Promise<IdentityRule[]> {
+  return invoke("load_mailmap", { path, mailmapPath: mailmapPath ?? null });
+}